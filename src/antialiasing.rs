@@ -0,0 +1,180 @@
+/*!
+# antialiasing.rs
+
+Smoothing of the jagged edges of triangles, by taking more than one
+sample per pixel.
+
+Supersampling (SSAA) renders the whole frame at a multiple of its size,
+on an ordered grid of samples, and averages it down. Multisampling (MSAA)
+tests the coverage and depth of the triangles at several positions in
+each pixel, but shades each pixel only once, so it costs far less. The
+MSAA sample positions are the standard 2x, 4x and 8x patterns, spread
+out so edges at any angle get several steps of coverage.
+*/
+
+use sdl2::pixels::Color;
+
+use crate::framebuffer::Framebuffer;
+
+/// Sample at the center of the pixel, without antialiasing
+const PATTERN_1X: [(f32, f32); 1] = [(0.5, 0.5)];
+/// Standard multisample positions in the pixel from its top left corner,
+/// on a grid of 1/16 of a pixel
+const PATTERN_2X: [(f32, f32); 2] = [(0.75, 0.75), (0.25, 0.25)];
+const PATTERN_4X: [(f32, f32); 4] = [(0.375, 0.125), (0.875, 0.375), (0.125, 0.625), (0.625, 0.875)];
+const PATTERN_8X: [(f32, f32); 8] = [
+    (0.5625, 0.3125), (0.4375, 0.6875), (0.8125, 0.5625), (0.3125, 0.1875),
+    (0.1875, 0.8125), (0.0625, 0.4375), (0.6875, 0.9375), (0.9375, 0.0625)
+];
+
+/// How edges are smoothed, with the number of samples per pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Antialiasing {
+    None,
+    /// Renders at this many times the pixels and averages them down
+    Ssaa(usize),
+    /// Tests this many samples per pixel, shading each pixel once
+    Msaa(usize)
+}
+
+impl Antialiasing {
+    /// Sample counts the modes support
+    pub const SAMPLES: [usize; 3] = [2, 4, 8];
+
+    /// The mode after this one, from no antialiasing through MSAA and
+    /// then SSAA with more and more samples
+    pub fn next(self) -> Self {
+        match self {
+            Antialiasing::None => Antialiasing::Msaa(2),
+            Antialiasing::Msaa(8) => Antialiasing::Ssaa(2),
+            Antialiasing::Msaa(samples) => Antialiasing::Msaa(samples * 2),
+            Antialiasing::Ssaa(8) => Antialiasing::None,
+            Antialiasing::Ssaa(samples) => Antialiasing::Ssaa(samples * 2)
+        }
+    }
+
+    /// Samples per pixel of the framebuffer, more than 1 only with MSAA
+    pub fn msaa_samples(self) -> usize {
+        match self {
+            Antialiasing::Msaa(samples) => samples,
+            _ => 1
+        }
+    }
+}
+
+/// Positions of the samples in a pixel, from its top left corner
+pub fn msaa_pattern(samples: usize) -> &'static [(f32, f32)] {
+    match samples {
+        1 => &PATTERN_1X,
+        2 => &PATTERN_2X,
+        4 => &PATTERN_4X,
+        8 => &PATTERN_8X,
+        _ => panic!("no pattern for {} samples", samples)
+    }
+}
+
+/// Columns and rows of samples per pixel when supersampling
+pub fn ssaa_grid(samples: usize) -> (usize, usize) {
+    match samples {
+        2 => (2, 1),
+        4 => (2, 2),
+        8 => (4, 2),
+        _ => panic!("no grid for {} samples", samples)
+    }
+}
+
+/// A framebuffer with a grid of samples for each pixel of the one given,
+/// starting from its colors
+pub fn supersample(framebuffer: &Framebuffer, (columns, rows): (usize, usize)) -> Framebuffer {
+    let mut supersampled = Framebuffer::new(framebuffer.width * columns, framebuffer.height * rows);
+    for y in 0..supersampled.height {
+        for x in 0..supersampled.width {
+            supersampled.set(x, y, framebuffer.get(x / columns, y / rows));
+        }
+    }
+    supersampled
+}
+
+/// Averages the grid of samples of each pixel back into the framebuffer,
+/// keeping the closest depth and the most overdraw
+pub fn downsample(supersampled: &Framebuffer, framebuffer: &mut Framebuffer, (columns, rows): (usize, usize)) {
+    let count = (columns * rows) as u32;
+    for y in 0..framebuffer.height {
+        for x in 0..framebuffer.width {
+            let mut sum = [0; 3];
+            let mut depth = f32::INFINITY;
+            let mut overdraw = 0;
+            for sample_y in y * rows..(y + 1) * rows {
+                for sample_x in x * columns..(x + 1) * columns {
+                    let color = supersampled.get(sample_x, sample_y);
+                    sum[0] += color.r as u32;
+                    sum[1] += color.g as u32;
+                    sum[2] += color.b as u32;
+                    let i = sample_y * supersampled.width + sample_x;
+                    depth = depth.min(supersampled.depth[i]);
+                    overdraw = overdraw.max(supersampled.overdraw[i]);
+                }
+            }
+            let average = sum.map(|channel| ((channel + count / 2) / count) as u8);
+            framebuffer.set(x, y, Color::RGB(average[0], average[1], average[2]));
+            let i = y * framebuffer.width + x;
+            framebuffer.depth[i] = depth;
+            framebuffer.overdraw[i] = overdraw;
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polygons::Tri;
+    use crate::raster;
+
+    #[test]
+    fn test_patterns() {
+        for samples in Antialiasing::SAMPLES {
+            let pattern = msaa_pattern(samples);
+            assert_eq!(pattern.len(), samples);
+            assert!(pattern.iter().all(|&(x, y)| x > 0.0 && x < 1.0 && y > 0.0 && y < 1.0));
+            let (columns, rows) = ssaa_grid(samples);
+            assert_eq!(columns * rows, samples);
+        }
+        assert_eq!(Antialiasing::Ssaa(8).next(), Antialiasing::None);
+    }
+
+    #[test]
+    fn test_msaa_edge() {
+        // A triangle cutting the pixels on its diagonal through their
+        // centers covers half of their samples
+        let mut framebuffer = Framebuffer::new(4, 4);
+        framebuffer.set_samples(4);
+        framebuffer.clear(Color::BLACK);
+        let mut tri = Tri::from([0.0, 0.0, 0.5, 4.0, 0.0, 0.5, 0.0, 4.0, 0.5]);
+        tri.c = Some(Color::WHITE);
+        raster::fill_triangle(&mut framebuffer, &tri);
+        framebuffer.resolve();
+
+        assert_eq!(framebuffer.get(0, 0), Color::WHITE);
+        assert_eq!(framebuffer.get(1, 2), Color::RGB(128, 128, 128));
+        assert_eq!(framebuffer.get(3, 3), Color::BLACK);
+        assert_eq!(framebuffer.depth[2 * 4 + 1], 0.5);
+    }
+
+    #[test]
+    fn test_supersample() {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.clear(Color::RED);
+        let mut supersampled = supersample(&framebuffer, (2, 2));
+        assert_eq!(supersampled.get(3, 1), Color::RED);
+
+        // A pixel with one sample of 4 blue
+        supersampled.set(0, 0, Color::BLUE);
+        supersampled.depth[0] = 0.25;
+        downsample(&supersampled, &mut framebuffer, (2, 2));
+        assert_eq!(framebuffer.get(0, 0), Color::RGB(191, 0, 64));
+        assert_eq!(framebuffer.get(1, 0), Color::RED);
+        assert_eq!(framebuffer.depth, vec![0.25, f32::INFINITY]);
+    }
+}
@@ -0,0 +1,159 @@
+/*!
+# bounds.rs
+
+Bounding volumes used to reason about where a mesh is in space
+without walking all of its triangles.
+
+* `Aabb`: an axis-aligned bounding box.
+* `BoundingSphere`: a sphere enclosing the mesh.
+*/
+
+use na::{Matrix4, Vector3, Vector4};
+
+/// An axis-aligned bounding box, given by its minimum and maximum corners
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>
+}
+
+/// A sphere that encloses a set of points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32
+}
+
+impl Aabb {
+    /// An inverted box that grows to fit the first point added to it
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3::repeat(f32::INFINITY),
+            max: Vector3::repeat(f32::NEG_INFINITY)
+        }
+    }
+
+    /// Whether no point has been added to the box yet
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    /// Grows the box so it contains the given point
+    pub fn add_point(&mut self, point: Vector3<f32>) {
+        self.min = self.min.inf(&point);
+        self.max = self.max.sup(&point);
+    }
+
+    /// Center of the box
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half of the size of the box along each axis
+    #[allow(dead_code)]
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// The 8 corners of the box
+    pub fn corners(&self) -> [Vector3<f32>; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vector3::new(a.x, a.y, a.z),
+            Vector3::new(b.x, a.y, a.z),
+            Vector3::new(a.x, b.y, a.z),
+            Vector3::new(b.x, b.y, a.z),
+            Vector3::new(a.x, a.y, b.z),
+            Vector3::new(b.x, a.y, b.z),
+            Vector3::new(a.x, b.y, b.z),
+            Vector3::new(b.x, b.y, b.z)
+        ]
+    }
+
+    /// The box that contains this one after being transformed by the matrix
+    #[allow(dead_code)]
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let mut aabb = Self::empty();
+        for corner in self.corners().iter() {
+            let p = matrix * Vector4::new(corner.x, corner.y, corner.z, 1.0);
+            aabb.add_point(p.xyz() / p.w);
+        }
+        aabb
+    }
+}
+
+impl BoundingSphere {
+    /// Creates a sphere from its center and radius
+    pub fn new(center: Vector3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// The sphere that contains this one after being transformed by the matrix
+    ///
+    /// The radius is scaled by the largest scale factor of the matrix,
+    /// so the result is conservative for non-uniform scales.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let c = self.center;
+        let center = matrix * Vector4::new(c.x, c.y, c.z, 1.0);
+        let scale = (0..3)
+            .map(|i| matrix.fixed_slice::<3, 1>(0, i).magnitude())
+            .fold(0.0, f32::max);
+        Self::new(center.xyz(), self.radius * scale)
+    }
+
+    /// The smallest sphere that contains both spheres
+    pub fn merge(&self, other: &Self) -> Self {
+        let offset = other.center - self.center;
+        let distance = offset.magnitude();
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        let center = self.center + offset * ((radius - self.radius) / distance);
+        Self::new(center, radius)
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_transforms;
+
+    #[test]
+    fn test_aabb() {
+        let mut aabb = Aabb::empty();
+        assert!(aabb.is_empty());
+        aabb.add_point(Vector3::new(1.0, -2.0, 3.0));
+        aabb.add_point(Vector3::new(-1.0, 2.0, 5.0));
+
+        assert_eq!(aabb.min, Vector3::new(-1.0, -2.0, 3.0));
+        assert_eq!(aabb.max, Vector3::new(1.0, 2.0, 5.0));
+        assert_eq!(aabb.center(), Vector3::new(0.0, 0.0, 4.0));
+        assert_eq!(aabb.extents(), Vector3::new(1.0, 2.0, 1.0));
+
+        let moved = aabb.transform(&linear_transforms::translation_matrix(1.0, 0.0, -4.0));
+        assert_eq!(moved.center(), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_bounding_sphere() {
+        let sphere = BoundingSphere::new(Vector3::new(1.0, 0.0, 0.0), 2.0);
+        let scale = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 3.0, 1.0));
+
+        let scaled = sphere.transform(&scale);
+        assert_eq!(scaled.center, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(scaled.radius, 6.0);
+
+        let other = BoundingSphere::new(Vector3::new(5.0, 0.0, 0.0), 1.0);
+        let merged = sphere.merge(&other);
+        assert_eq!(merged.center, Vector3::new(2.5, 0.0, 0.0));
+        assert_eq!(merged.radius, 3.5);
+        assert_eq!(merged.merge(&other), merged);
+    }
+}
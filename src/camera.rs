@@ -0,0 +1,126 @@
+/*!
+# camera.rs
+
+The camera the scene is seen from.
+
+Holds the position, orientation and lens of the viewer and builds
+the view and projection matrices from them.
+*/
+
+use na::{Matrix4, Rotation3, Unit, Vector3};
+
+use crate::bounds::BoundingSphere;
+use crate::linear_transforms;
+
+/// A perspective camera
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Vector3<f32>,
+    /// Direction the camera is looking at (normalized)
+    pub direction: Vector3<f32>,
+    pub up: Vector3<f32>,
+    /// Vertical field of view in degrees
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32
+}
+
+impl Camera {
+    /// Creates a camera in the given position looking in the given direction
+    pub fn new(position: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            fov: 60.0,
+            near: 0.1,
+            far: 1000.0
+        }
+    }
+
+    /// Unit vector pointing to the right of the camera
+    pub fn right(&self) -> Vector3<f32> {
+        self.direction.cross(&self.up).normalize()
+    }
+
+    /// Moves the camera along its own axes
+    ///
+    /// `offset.x` moves to the right, `offset.y` up and `offset.z` forward.
+    pub fn move_local(&mut self, offset: Vector3<f32>) {
+        self.position += self.right() * offset.x + self.up * offset.y + self.direction * offset.z;
+    }
+
+    /// Turns the camera, `yaw` radians to the right around its up
+    /// axis and `pitch` radians up
+    ///
+    /// The pitch stops just short of looking straight up or down,
+    /// where the right of the camera would be undefined.
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        const MIN_ANGLE: f32 = 0.01;
+        let angle_from_up = self.direction.dot(&self.up).clamp(-1.0, 1.0).acos();
+        let pitch = pitch.clamp(angle_from_up - (std::f32::consts::PI - MIN_ANGLE), angle_from_up - MIN_ANGLE);
+
+        let right = self.right();
+        let yaw_rotation = Rotation3::from_axis_angle(&Unit::new_normalize(self.up), -yaw);
+        let pitch_rotation = Rotation3::from_axis_angle(&Unit::new_normalize(right), pitch);
+        self.direction = (yaw_rotation * pitch_rotation * self.direction).normalize();
+    }
+
+    /// Matrix that moves the world into the camera's point of view
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        linear_transforms::view_matrix(self.position, self.direction, self.up)
+    }
+
+    /// Projection matrix for a screen with the given aspect ratio
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Matrix4<f32> {
+        linear_transforms::projection_matrix(self.fov, aspect_ratio, self.near, self.far)
+    }
+
+    /// Moves the camera back along its direction until the sphere
+    /// fills the view, and fits the near and far planes around it
+    pub fn frame(&mut self, sphere: &BoundingSphere, aspect_ratio: f32) {
+        let half_fov_y = (self.fov * 0.5).to_radians();
+        let half_fov_x = (half_fov_y.tan() * aspect_ratio).atan();
+        let half_fov = half_fov_y.min(half_fov_x);
+
+        let radius = sphere.radius.max(f32::EPSILON);
+        let distance = radius / half_fov.sin();
+        self.position = sphere.center - self.direction * distance;
+
+        // Leave some room so the model doesn't clip while moving around
+        self.near = ((distance - radius) * 0.5).max(radius * 0.01);
+        self.far = distance + radius * 4.0;
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame() {
+        let mut camera = Camera::new(Vector3::zeros(), Vector3::new(0.0, 0.0, -1.0));
+        let sphere = BoundingSphere::new(Vector3::new(0.0, 0.0, -10.0), 1.0);
+        camera.frame(&sphere, 1.0);
+
+        // 60 degrees of fov, so the sphere is 2 radii away
+        assert!((camera.position - Vector3::new(0.0, 0.0, -8.0)).magnitude() < 1e-5);
+        assert!(camera.near > 0.0 && camera.near < 1.0);
+        assert!(camera.far > 3.0);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut camera = Camera::new(Vector3::zeros(), Vector3::new(0.0, 0.0, 1.0));
+        let right = camera.right();
+        camera.rotate(std::f32::consts::FRAC_PI_2, 0.0);
+        assert!((camera.direction - right).magnitude() < 1e-5);
+
+        // Looking up stops before the camera flips over
+        camera.rotate(0.0, 10.0);
+        assert!(camera.direction.y > 0.99 && camera.direction.y < 1.0);
+        assert!(camera.right().iter().all(|c| c.is_finite()));
+    }
+}
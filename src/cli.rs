@@ -0,0 +1,476 @@
+/*!
+# cli.rs
+
+Command line options of the renderer.
+
+```text
+graphics-from-scratch [OPTIONS] [FILE]
+```
+
+`FILE` is either a scene description (`.toml`) or a single model (`.obj`).
+With `--output` the frames are rendered to images instead of a window.
+*/
+
+use na::Vector3;
+
+use crate::antialiasing::Antialiasing;
+use crate::culling::{CullMode, FrontFace};
+use crate::lighting::Shading;
+use crate::recording::RecordFormat;
+use crate::render::{DebugView, RenderMode};
+use crate::texture::TextureFilter;
+use crate::transparency::Transparency;
+
+/// Options given in the command line
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// Scene or model to load
+    pub path: String,
+    pub width: usize,
+    pub height: usize,
+    pub fps: u64,
+    pub title: String,
+    /// Overrides the field of view of the scene, in degrees
+    pub fov: Option<f32>,
+    pub render_mode: RenderMode,
+    /// What the triangles are colored with to debug the renderer
+    pub debug_view: DebugView,
+    /// Draw the normals of the triangles as lines
+    pub show_normals: bool,
+    /// Width of the wireframe and normal lines, in pixels
+    pub line_width: f32,
+    pub antialiasing: Antialiasing,
+    /// How transparent triangles are blended together
+    pub transparency: Transparency,
+    /// How textures are sampled
+    pub texture_filter: TextureFilter,
+    /// Light per pixel with the shadows of the lights
+    pub shadows: bool,
+    /// Bend the lighting of materials with their normal maps
+    pub normal_maps: bool,
+    /// Threads rendering the frames, all the cores by default
+    pub threads: Option<usize>,
+    pub shading: Shading,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    /// Overrides the position of the camera
+    pub camera_position: Option<Vector3<f32>>,
+    /// Point the camera looks at, overriding its direction
+    pub camera_target: Option<Vector3<f32>>,
+    /// Image to render to, without opening a window
+    pub output: Option<String>,
+    /// Frames of the turntable rendered to images
+    pub frames: usize,
+    /// Directory screenshots are saved to
+    pub screenshot_dir: String,
+    /// Save the depth buffer next to screenshots and rendered images
+    pub save_depth: bool,
+    /// Directory recordings are saved to
+    pub record_dir: String,
+    pub record_format: RecordFormat,
+    /// Font of the on-screen overlay
+    pub font: String,
+    /// Show the overlay from the start
+    pub hud: bool,
+    /// Rate of fixed simulation steps, in steps per second,
+    /// instead of a step per frame
+    pub fixed_step: Option<f64>,
+    /// File changing the key bindings
+    pub bindings: Option<String>,
+    /// Start in fullscreen
+    pub fullscreen: bool,
+    /// Size of the rendered frame relative to the window
+    pub render_scale: f32
+}
+
+/// What the command line asks for
+// Parsed once at startup, the size of the options doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Help
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            path: "assets/scene.toml".to_string(),
+            width: 800,
+            height: 600,
+            fps: 60,
+            title: "Rust 3D Renderer".to_string(),
+            fov: None,
+            render_mode: RenderMode::Filled,
+            debug_view: DebugView::None,
+            show_normals: false,
+            line_width: 1.0,
+            antialiasing: Antialiasing::None,
+            transparency: Transparency::Sorted,
+            texture_filter: TextureFilter::Trilinear,
+            shadows: false,
+            normal_maps: true,
+            threads: None,
+            shading: Shading::Flat,
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+            camera_position: None,
+            camera_target: None,
+            output: None,
+            frames: 1,
+            screenshot_dir: "screenshots".to_string(),
+            save_depth: false,
+            record_dir: "recordings".to_string(),
+            record_format: RecordFormat::Gif,
+            font: "assets/fonts/DejaVuSansMono.ttf".to_string(),
+            hud: true,
+            fixed_step: None,
+            bindings: None,
+            fullscreen: false,
+            render_scale: 1.0
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments, without the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+        let mut options = Self::default();
+        let mut path = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // Accept both `--option value` and `--option=value`
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+                _ => (arg.clone(), None)
+            };
+            let mut value = || inline_value.clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for {}", name));
+
+            match name.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "-s" | "--size" => {
+                    let size = value()?;
+                    let (width, height) = size.split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .filter(|&(w, h)| w > 0 && h > 0)
+                        .ok_or_else(|| format!("invalid size \"{}\", expected WIDTHxHEIGHT", size))?;
+                    options.width = width;
+                    options.height = height;
+                },
+                "--fps" => {
+                    options.fps = parse_number(&name, &value()?)?;
+                    if options.fps == 0 {
+                        return Err("--fps has to be at least 1".to_string());
+                    }
+                },
+                "--title" => options.title = value()?,
+                "--fov" => {
+                    let fov = parse_number(&name, &value()?)?;
+                    if !(fov > 0.0 && fov < 180.0) {
+                        return Err("--fov has to be between 0 and 180 degrees".to_string());
+                    }
+                    options.fov = Some(fov);
+                },
+                "--render-mode" => options.render_mode = match value()?.as_str() {
+                    "filled" => RenderMode::Filled,
+                    "wireframe" => RenderMode::Wireframe,
+                    "filled-wireframe" => RenderMode::FilledWireframe,
+                    "points" => RenderMode::Points,
+                    "gfx" => RenderMode::Gfx,
+                    other => return Err(format!(
+                        "unknown render mode \"{}\", expected filled, wireframe, filled-wireframe, points or gfx", other
+                    ))
+                },
+                "--debug" => options.debug_view = match value()?.as_str() {
+                    "none" => DebugView::None,
+                    "face-normals" => DebugView::FaceNormals,
+                    "vertex-normals" => DebugView::VertexNormals,
+                    "depth" => DebugView::Depth,
+                    "uvs" => DebugView::Uvs,
+                    "triangle-ids" => DebugView::TriangleIds,
+                    "overdraw" => DebugView::Overdraw,
+                    other => return Err(format!(
+                        "unknown debug view \"{}\", expected none, face-normals, vertex-normals, depth, uvs, triangle-ids or overdraw",
+                        other
+                    ))
+                },
+                "--normals" => options.show_normals = true,
+                "--shadows" => options.shadows = true,
+                "--no-normal-maps" => options.normal_maps = false,
+                "--line-width" => {
+                    options.line_width = parse_number(&name, &value()?)?;
+                    if !(0.5..=16.0).contains(&options.line_width) {
+                        return Err("--line-width has to be between 0.5 and 16 pixels".to_string());
+                    }
+                },
+                "--threads" => {
+                    let threads = parse_number(&name, &value()?)?;
+                    if threads == 0 {
+                        return Err("--threads has to be at least 1".to_string());
+                    }
+                    options.threads = Some(threads);
+                },
+                "--antialiasing" => options.antialiasing = parse_antialiasing(&value()?)?,
+                "--transparency" => options.transparency = match value()?.as_str() {
+                    "sorted" => Transparency::Sorted,
+                    "weighted" => Transparency::Weighted,
+                    other => return Err(format!("unknown transparency \"{}\", expected sorted or weighted", other))
+                },
+                "--texture-filter" => options.texture_filter = parse_texture_filter(&value()?)?,
+                "--shading" => options.shading = match value()?.as_str() {
+                    "flat" => Shading::Flat,
+                    "phong" => Shading::Phong,
+                    "unlit" => Shading::Unlit,
+                    other => return Err(format!("unknown shading \"{}\", expected flat, phong or unlit", other))
+                },
+                "--cull" => options.cull_mode = match value()?.as_str() {
+                    "back" => CullMode::Back,
+                    "front" => CullMode::Front,
+                    "none" => CullMode::None,
+                    other => return Err(format!("unknown cull mode \"{}\", expected back, front or none", other))
+                },
+                "--front-face" => options.front_face = match value()?.as_str() {
+                    "ccw" => FrontFace::CounterClockwise,
+                    "cw" => FrontFace::Clockwise,
+                    other => return Err(format!("unknown front face \"{}\", expected ccw or cw", other))
+                },
+                "--camera-position" => options.camera_position = Some(parse_vector(&name, &value()?)?),
+                "--camera-target" => options.camera_target = Some(parse_vector(&name, &value()?)?),
+                "-o" | "--output" => options.output = Some(value()?),
+                "--frames" => {
+                    options.frames = parse_number(&name, &value()?)?;
+                    if options.frames == 0 {
+                        return Err("--frames has to be at least 1".to_string());
+                    }
+                },
+                "--screenshot-dir" => options.screenshot_dir = value()?,
+                "--depth" => options.save_depth = true,
+                "--record-dir" => options.record_dir = value()?,
+                "--record-format" => options.record_format = match value()?.as_str() {
+                    "frames" => RecordFormat::Frames,
+                    "gif" => RecordFormat::Gif,
+                    "y4m" => RecordFormat::Y4m,
+                    other => return Err(format!("unknown record format \"{}\", expected frames, gif or y4m", other))
+                },
+                "--font" => options.font = value()?,
+                "--no-hud" => options.hud = false,
+                "--bindings" => options.bindings = Some(value()?),
+                "--fullscreen" => options.fullscreen = true,
+                "--render-scale" => {
+                    let scale: f32 = parse_number(&name, &value()?)?;
+                    if !(0.1..=4.0).contains(&scale) {
+                        return Err("--render-scale has to be between 0.1 and 4".to_string());
+                    }
+                    options.render_scale = scale;
+                },
+                "--fixed-step" => {
+                    let rate: f64 = parse_number(&name, &value()?)?;
+                    if !(0.0..=1000.0).contains(&rate) || rate == 0.0 {
+                        return Err("--fixed-step has to be above 0 and at most 1000".to_string());
+                    }
+                    options.fixed_step = Some(rate);
+                },
+                _ if name.starts_with('-') => return Err(format!("unknown option {}", name)),
+                _ => {
+                    if path.is_some() {
+                        return Err(format!("unexpected argument \"{}\", only one file can be given", arg));
+                    }
+                    path = Some(arg);
+                }
+            }
+        }
+
+        if let Some(path) = path {
+            options.path = path;
+        }
+        Ok(Command::Run(options))
+    }
+
+    /// Whether the file to load is a single model instead of a scene
+    pub fn is_model(&self) -> bool {
+        self.path.to_lowercase().ends_with(".obj")
+    }
+}
+
+/// Help text listing the options
+pub fn usage() -> String {
+    let defaults = Options::default();
+    format!("\
+A basic cpu renderer written in Rust.
+
+Usage: graphics-from-scratch [OPTIONS] [FILE]
+
+Arguments:
+  [FILE]  Scene (.toml) or model (.obj) to render [default: {}]
+
+Options:
+  -s, --size <WIDTHxHEIGHT>  Size of the window [default: {}x{}]
+      --fullscreen           Start in fullscreen
+      --render-scale <SCALE> Render at this scale of the window and stretch it to fit [default: 1]
+      --fps <FPS>            Frame rate cap [default: {}]
+      --fixed-step <HZ>      Simulate in fixed steps at this rate, instead of once per frame
+      --threads <N>          Threads rendering the frames [default: one per core]
+      --title <TITLE>        Title of the window [default: {}]
+      --fov <DEGREES>        Vertical field of view, overrides the scene's
+      --render-mode <MODE>   filled, wireframe, filled-wireframe, points or gfx [default: filled]
+      --debug <VIEW>         Color the triangles with face-normals, vertex-normals, depth, uvs,
+                             triangle-ids or overdraw [default: none]
+      --normals              Draw the normals of the triangles as lines
+      --line-width <PIXELS>  Width of the anti-aliased wireframe and normal lines [default: 1]
+      --antialiasing <MODE>  none, or msaa or ssaa with 2, 4 or 8 samples, like msaa4 [default: none]
+      --transparency <METHOD>
+                             Blend transparent triangles sorted back to front, or with weighted
+                             order-independent transparency: sorted or weighted [default: sorted]
+      --texture-filter <FILTER>
+                             nearest, bilinear, trilinear, or anisotropic with 2, 4, 8 or 16
+                             samples, like aniso8 [default: trilinear]
+      --shading <MODE>       flat, phong (smooth, lit per pixel) or unlit [default: flat]
+      --shadows              Cast shadows from the lights, lighting per pixel
+      --no-normal-maps       Ignore the normal maps of the materials
+      --cull <MODE>          Faces to cull: back, front or none [default: back]
+      --front-face <ORDER>   Winding of front faces: ccw or cw [default: ccw]
+      --camera-position <X,Y,Z>
+                             Position of the camera, overrides the scene's
+      --camera-target <X,Y,Z>
+                             Point the camera looks at
+  -o, --output <FILE>        Render to a .png or .ppm image instead of a window
+      --frames <N>           Render a turntable of N images around the scene [default: 1]
+      --screenshot-dir <DIR> Directory screenshots are saved to [default: {}]
+      --depth                Also save the depth buffer as a grayscale image
+      --record-dir <DIR>     Directory recordings are saved to [default: {}]
+      --record-format <FORMAT>
+                             frames (numbered PNGs), gif or y4m [default: gif]
+      --font <FILE>          TrueType font of the overlay [default: {}]
+      --no-hud               Hide the overlay until H is pressed
+      --bindings <FILE>      Key bindings to use instead of the defaults (.toml)
+  -h, --help                 Print this help",
+        defaults.path, defaults.width, defaults.height, defaults.fps, defaults.title, defaults.screenshot_dir,
+        defaults.record_dir, defaults.font
+    )
+}
+
+fn parse_vector(name: &str, value: &str) -> Result<Vector3<f32>, String> {
+    let components: Vec<f32> = value.split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid value \"{}\" for {}, expected X,Y,Z", value, name))?;
+    match components[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => Err(format!("invalid value \"{}\" for {}, expected X,Y,Z", value, name))
+    }
+}
+
+fn parse_antialiasing(value: &str) -> Result<Antialiasing, String> {
+    let error = || format!(
+        "unknown antialiasing \"{}\", expected none, msaa2, msaa4, msaa8, ssaa2, ssaa4 or ssaa8", value
+    );
+    let samples = |count: &str| count.parse().ok()
+        .filter(|count| Antialiasing::SAMPLES.contains(count))
+        .ok_or_else(error);
+    if value == "none" {
+        Ok(Antialiasing::None)
+    } else if let Some(count) = value.strip_prefix("msaa") {
+        Ok(Antialiasing::Msaa(samples(count)?))
+    } else if let Some(count) = value.strip_prefix("ssaa") {
+        Ok(Antialiasing::Ssaa(samples(count)?))
+    } else {
+        Err(error())
+    }
+}
+
+fn parse_texture_filter(value: &str) -> Result<TextureFilter, String> {
+    let error = || format!(
+        "unknown texture filter \"{}\", expected nearest, bilinear, trilinear, aniso2, aniso4, aniso8 or aniso16", value
+    );
+    match value {
+        "nearest" => Ok(TextureFilter::Nearest),
+        "bilinear" => Ok(TextureFilter::Bilinear),
+        "trilinear" => Ok(TextureFilter::Trilinear),
+        _ => value.strip_prefix("aniso")
+            .and_then(|count| count.parse().ok())
+            .filter(|count| TextureFilter::ANISOTROPY.contains(count))
+            .map(TextureFilter::Anisotropic)
+            .ok_or_else(error)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value \"{}\" for {}", value, name))
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Options::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let command = parse(&[
+            "model.obj", "--size", "1280x720", "--fov=75", "--shading", "phong", "--cull", "none", "--line-width", "2.5",
+            "--threads", "1", "--shadows", "--no-normal-maps"
+        ]);
+        let expected = Options {
+            path: "model.obj".to_string(),
+            width: 1280,
+            height: 720,
+            fov: Some(75.0),
+            line_width: 2.5,
+            threads: Some(1),
+            shadows: true,
+            normal_maps: false,
+            shading: Shading::Phong,
+            cull_mode: CullMode::None,
+            ..Options::default()
+        };
+        assert_eq!(command, Ok(Command::Run(expected)));
+        assert_eq!(parse(&["-h"]), Ok(Command::Help));
+
+        let command = parse(&[
+            "-o", "thumb.png", "--frames", "12", "--camera-position", "1,2.5,-3", "--depth", "--debug", "overdraw",
+            "--antialiasing", "ssaa4", "--transparency", "weighted", "--texture-filter", "aniso8"
+        ]);
+        let expected = Options {
+            output: Some("thumb.png".to_string()),
+            frames: 12,
+            save_depth: true,
+            debug_view: DebugView::Overdraw,
+            antialiasing: Antialiasing::Ssaa(4),
+            transparency: Transparency::Weighted,
+            texture_filter: TextureFilter::Anisotropic(8),
+            camera_position: Some(Vector3::new(1.0, 2.5, -3.0)),
+            ..Options::default()
+        };
+        assert_eq!(command, Ok(Command::Run(expected)));
+        assert_eq!(parse(&[]), Ok(Command::Run(Options::default())));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&["--size", "1280"]).is_err());
+        assert!(parse(&["--fov"]).is_err());
+        assert!(parse(&["--cull", "sideways"]).is_err());
+        assert!(parse(&["--shading", "gouraud"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+        assert!(parse(&["--camera-target", "1,2"]).is_err());
+        assert!(parse(&["--frames", "0"]).is_err());
+        assert!(parse(&["--record-format", "avi"]).is_err());
+        assert!(parse(&["--debug", "normals"]).is_err());
+        assert!(parse(&["--line-width", "0"]).is_err());
+        assert!(parse(&["--antialiasing", "msaa3"]).is_err());
+        assert!(parse(&["--threads", "0"]).is_err());
+        assert!(parse(&["--antialiasing", "fxaa4"]).is_err());
+        assert!(parse(&["--transparency", "depth-peeling"]).is_err());
+        assert!(parse(&["--texture-filter", "aniso3"]).is_err());
+        assert!(parse(&["--fixed-step", "0"]).is_err());
+        assert!(parse(&["--render-scale", "0"]).is_err());
+        assert!(parse(&["a.obj", "b.obj"]).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+/*!
+# clock.rs
+
+Frame timing on a monotonic clock.
+
+The simulation advances by the time that passed since the last frame,
+so movement and animation take the same time at any frame rate. It can
+also run in fixed steps, accumulating the frame time until a whole step
+has passed, which makes it deterministic.
+*/
+
+use std::time::{Duration, Instant};
+
+/// Longest time a frame can advance the simulation, so a stall (like
+/// dragging the window) doesn't make everything jump
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+/// Measures the time between frames and splits it in simulation steps
+pub struct Clock {
+    last_tick: Instant,
+    /// Length of the simulation steps, or `None` for one step per frame
+    fixed_step: Option<Duration>,
+    /// Time not simulated yet, shorter than a fixed step
+    accumulator: Duration
+}
+
+impl Clock {
+    pub fn new(fixed_step: Option<Duration>) -> Self {
+        Self {
+            last_tick: Instant::now(),
+            fixed_step,
+            accumulator: Duration::ZERO
+        }
+    }
+
+    /// Time since the last tick, capped to `MAX_FRAME_TIME`
+    pub fn tick(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+        elapsed.min(MAX_FRAME_TIME)
+    }
+
+    /// Simulation steps covering the elapsed time, in seconds
+    ///
+    /// With fixed steps, what is left of the time carries over to the next frame.
+    pub fn steps(&mut self, elapsed: Duration) -> Vec<f32> {
+        match self.fixed_step {
+            None => vec![elapsed.as_secs_f32()],
+            Some(step) => {
+                self.accumulator += elapsed;
+                let count = (self.accumulator.as_nanos() / step.as_nanos()) as u32;
+                self.accumulator -= step * count;
+                vec![step.as_secs_f32(); count as usize]
+            }
+        }
+    }
+}
+
+/// Keeps frames from coming faster than a frame rate
+pub struct FrameLimiter {
+    period: Duration,
+    next_frame: Instant
+}
+
+impl FrameLimiter {
+    pub fn new(fps: u64) -> Self {
+        Self {
+            period: Duration::from_secs_f64(1.0 / fps as f64),
+            next_frame: Instant::now()
+        }
+    }
+
+    /// Sleeps until it is time for the next frame
+    ///
+    /// Frames are scheduled one period after the other, so sleeping a bit
+    /// too long doesn't lower the frame rate. A late frame starts over from now.
+    pub fn wait(&mut self) {
+        self.next_frame += self.period;
+        let now = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+        } else {
+            self.next_frame = now;
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps() {
+        let mut clock = Clock::new(None);
+        assert_eq!(clock.steps(Duration::from_millis(40)), vec![0.04]);
+
+        // 10 ms steps, the leftover time is simulated in later frames
+        let mut clock = Clock::new(Some(Duration::from_millis(10)));
+        assert_eq!(clock.steps(Duration::from_millis(25)).len(), 2);
+        assert_eq!(clock.steps(Duration::from_millis(4)).len(), 0);
+        assert_eq!(clock.steps(Duration::from_millis(1)).len(), 1);
+    }
+}
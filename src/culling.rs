@@ -0,0 +1,89 @@
+/*!
+# culling.rs
+
+Back-face culling configuration.
+
+Faces are classified by their winding once projected to the screen,
+so the test doesn't depend on which space the normals live in.
+*/
+
+use crate::polygons::Tri;
+
+/// Which faces are discarded before rasterizing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    Back,
+    Front,
+    None
+}
+
+/// Winding order of the vertices of a front face, as seen on screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    Clockwise,
+    CounterClockwise
+}
+
+impl CullMode {
+    /// The next mode, for cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            CullMode::Back => CullMode::Front,
+            CullMode::Front => CullMode::None,
+            CullMode::None => CullMode::Back
+        }
+    }
+
+    /// Whether a face with the given facing should be discarded
+    pub fn culls(self, front_facing: bool) -> bool {
+        match self {
+            CullMode::Back => !front_facing,
+            CullMode::Front => front_facing,
+            CullMode::None => false
+        }
+    }
+}
+
+impl FrontFace {
+    /// The other winding order
+    pub fn flip(self) -> Self {
+        match self {
+            FrontFace::Clockwise => FrontFace::CounterClockwise,
+            FrontFace::CounterClockwise => FrontFace::Clockwise
+        }
+    }
+
+    /// Whether the projected triangle is facing the camera
+    ///
+    /// Expects the triangle in screen space, with y pointing down.
+    pub fn is_front_facing(self, projected: &Tri) -> bool {
+        let area = projected.signed_area();
+        match self {
+            FrontFace::Clockwise => area > 0.0,
+            FrontFace::CounterClockwise => area < 0.0
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winding() {
+        // Right, then up on a y-down screen: counter-clockwise
+        let ccw = Tri::from([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, -1.0, 0.0]);
+        let cw = Tri::from([0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0]);
+
+        assert!(FrontFace::CounterClockwise.is_front_facing(&ccw));
+        assert!(!FrontFace::CounterClockwise.is_front_facing(&cw));
+        assert!(FrontFace::Clockwise.is_front_facing(&cw));
+
+        assert!(CullMode::Back.culls(false));
+        assert!(!CullMode::Back.culls(true));
+        assert!(CullMode::Front.culls(true));
+        assert!(!CullMode::None.culls(true) && !CullMode::None.culls(false));
+    }
+}
@@ -0,0 +1,297 @@
+/*!
+# framebuffer.rs
+
+An image in memory the renderer draws into, with a color and a depth
+value per pixel. It doesn't depend on a window, so frames can be
+rendered headless and saved to PNG or PPM files.
+
+With multisampling, triangles are drawn into a color and a depth for
+each sample of each pixel, then resolved into the pixels.
+*/
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use sdl2::image::SaveSurface;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::surface::Surface;
+
+/// Color and depth buffers of a frame
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    /// RGB24 pixels, row by row from the top left corner
+    pub pixels: Vec<u8>,
+    /// Depth of each pixel, in the [0, 1] range of the projection
+    pub depth: Vec<f32>,
+    /// Times each pixel was written by a triangle in the frame
+    pub overdraw: Vec<u32>,
+    /// Samples per pixel with multisampling, 1 without
+    pub samples: usize,
+    /// RGB24 color of each sample, pixel after pixel, empty without multisampling
+    pub sample_pixels: Vec<u8>,
+    /// Depth of each sample, empty without multisampling
+    pub sample_depth: Vec<f32>,
+    /// Position of the top left pixel in the frame, when this is a tile of it
+    pub origin: (usize, usize)
+}
+
+impl Framebuffer {
+    /// Creates a black framebuffer of the given size
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 3],
+            depth: vec![f32::INFINITY; width * height],
+            overdraw: vec![0; width * height],
+            samples: 1,
+            sample_pixels: Vec::new(),
+            sample_depth: Vec::new(),
+            origin: (0, 0)
+        }
+    }
+
+    /// Copy of a rectangle of the framebuffer with its samples, to draw
+    /// into on its own and put back with `put_tile`
+    pub fn tile(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        let mut tile = Self::new(width, height);
+        tile.origin = (x, y);
+        tile.samples = self.samples;
+        for row in 0..height {
+            let from = (y + row) * self.width + x;
+            let to = row * width;
+            tile.pixels[to * 3..(to + width) * 3].copy_from_slice(&self.pixels[from * 3..(from + width) * 3]);
+            tile.depth[to..to + width].copy_from_slice(&self.depth[from..from + width]);
+            tile.overdraw[to..to + width].copy_from_slice(&self.overdraw[from..from + width]);
+            if self.samples > 1 {
+                let samples = self.samples;
+                tile.sample_pixels.extend_from_slice(&self.sample_pixels[from * samples * 3..(from + width) * samples * 3]);
+                tile.sample_depth.extend_from_slice(&self.sample_depth[from * samples..(from + width) * samples]);
+            }
+        }
+        tile
+    }
+
+    /// Copies a tile taken with `tile` back where it came from
+    pub fn put_tile(&mut self, tile: &Framebuffer) {
+        let (x, y) = tile.origin;
+        let width = tile.width;
+        let samples = self.samples;
+        for row in 0..tile.height {
+            let to = (y + row) * self.width + x;
+            let from = row * width;
+            self.pixels[to * 3..(to + width) * 3].copy_from_slice(&tile.pixels[from * 3..(from + width) * 3]);
+            self.depth[to..to + width].copy_from_slice(&tile.depth[from..from + width]);
+            self.overdraw[to..to + width].copy_from_slice(&tile.overdraw[from..from + width]);
+            if samples > 1 {
+                self.sample_pixels[to * samples * 3..(to + width) * samples * 3]
+                    .copy_from_slice(&tile.sample_pixels[from * samples * 3..(from + width) * samples * 3]);
+                self.sample_depth[to * samples..(to + width) * samples]
+                    .copy_from_slice(&tile.sample_depth[from * samples..(from + width) * samples]);
+            }
+        }
+    }
+
+    /// Changes the number of samples per pixel, starting every sample
+    /// from the color of its pixel
+    pub fn set_samples(&mut self, samples: usize) {
+        if samples == self.samples {
+            return;
+        }
+        self.samples = samples;
+        if samples == 1 {
+            self.sample_pixels = Vec::new();
+            self.sample_depth = Vec::new();
+        } else {
+            self.sample_pixels = self.pixels.chunks_exact(3).flat_map(|pixel| pixel.repeat(samples)).collect();
+            self.sample_depth = vec![f32::INFINITY; self.width * self.height * samples];
+        }
+    }
+
+    /// Averages the samples of each pixel into its color, and keeps the
+    /// closest of their depths, nothing to do without multisampling
+    pub fn resolve(&mut self) {
+        if self.samples == 1 {
+            return;
+        }
+        let samples = self.samples as u32;
+        let pixel_samples = self.sample_pixels.chunks_exact(3 * self.samples);
+        let depth_samples = self.sample_depth.chunks_exact(self.samples);
+        for (i, (colors, depths)) in pixel_samples.zip(depth_samples).enumerate() {
+            for channel in 0..3 {
+                let sum: u32 = colors.iter().skip(channel).step_by(3).map(|&c| c as u32).sum();
+                self.pixels[i * 3 + channel] = ((sum + samples / 2) / samples) as u8;
+            }
+            self.depth[i] = depths.iter().copied().fold(f32::INFINITY, f32::min);
+        }
+    }
+
+    /// Fills the color buffer with the color and resets the depth buffer
+    pub fn clear(&mut self, color: Color) {
+        for pixel in self.pixels.chunks_exact_mut(3) {
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+        self.depth.fill(f32::INFINITY);
+        self.overdraw.fill(0);
+        for sample in self.sample_pixels.chunks_exact_mut(3) {
+            sample.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+        self.sample_depth.fill(f32::INFINITY);
+    }
+
+    /// Color of the pixel
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        let i = (y * self.width + x) * 3;
+        Color::RGB(self.pixels[i], self.pixels[i + 1], self.pixels[i + 2])
+    }
+
+    /// Sets the color of the pixel
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        let i = (y * self.width + x) * 3;
+        self.pixels[i..i + 3].copy_from_slice(&[color.r, color.g, color.b]);
+    }
+
+    /// Mixes the color into the pixel, `alpha` going from 0 to keep the
+    /// pixel as it is to 1 to replace it
+    pub fn blend(&mut self, x: usize, y: usize, color: Color, alpha: f32) {
+        let i = (y * self.width + x) * 3;
+        for (channel, value) in self.pixels[i..i + 3].iter_mut().zip([color.r, color.g, color.b]) {
+            *channel = (*channel as f32 + (value as f32 - *channel as f32) * alpha).round() as u8;
+        }
+    }
+
+    /// Saves the color buffer to an image, PNG or PPM depending on the extension
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        save_rgb(path, self.width, self.height, &self.pixels)
+    }
+
+    /// Distance from the camera of each pixel, undoing the projection
+    /// with its near and far planes
+    ///
+    /// Pixels nothing was drawn to are infinitely far away.
+    pub fn linear_depth(&self, near: f32, far: f32) -> Vec<f32> {
+        self.depth.iter()
+            .map(|&z| if z.is_finite() { near * far / (far - z * (far - near)) } else { f32::INFINITY })
+            .collect()
+    }
+
+    /// The depth buffer as a grayscale RGB24 image, from white for the
+    /// closest pixel to black for the furthest one and the background
+    pub fn depth_image(&self, near: f32, far: f32) -> Vec<u8> {
+        let depth = self.linear_depth(near, far);
+        let finite = depth.iter().filter(|z| z.is_finite());
+        let min = finite.clone().fold(f32::INFINITY, |a, &b| a.min(b));
+        let max = finite.fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+        let range = (max - min).max(f32::EPSILON);
+
+        depth.iter()
+            .flat_map(|&z| {
+                // Keep the furthest pixels a bit brighter than the background
+                let gray = if z.is_finite() { (1.0 - (z - min) / range) * 223.0 + 32.0 } else { 0.0 };
+                [gray as u8; 3]
+            })
+            .collect()
+    }
+
+    /// Saves the depth buffer as a grayscale image
+    pub fn save_depth(&self, path: &str, near: f32, far: f32) -> Result<(), String> {
+        save_rgb(path, self.width, self.height, &self.depth_image(near, far))
+    }
+}
+
+/// Size of the frame rendered for a window, at a scale of its size
+///
+/// A scale under 1 renders fewer pixels and stretches them to fill the window.
+pub fn render_size(window_width: u32, window_height: u32, scale: f32) -> (usize, usize) {
+    let scaled = |size: u32| ((size as f32 * scale).round() as usize).max(1);
+    (scaled(window_width), scaled(window_height))
+}
+
+/// Saves RGB24 pixels to an image, PNG or PPM depending on the extension
+pub fn save_rgb(path: &str, width: usize, height: usize, pixels: &[u8]) -> Result<(), String> {
+    let extension = Path::new(path).extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => save_png(path, width, height, pixels),
+        "ppm" => save_ppm(path, width, height, pixels),
+        _ => Err(format!("{}: unknown image format, expected .png or .ppm", path))
+    }
+}
+
+/// Saves the pixels as a PNG image, through SDL_image
+fn save_png(path: &str, width: usize, height: usize, pixels: &[u8]) -> Result<(), String> {
+    let mut data = pixels.to_vec();
+    let surface = Surface::from_data(
+        &mut data,
+        width as u32,
+        height as u32,
+        width as u32 * 3,
+        PixelFormatEnum::RGB24
+    )?;
+    surface.save(path)
+}
+
+/// Path with `suffix` added to the name of the file, before the extension
+///
+/// `with_suffix("shots/frame.png", "_depth")` is `shots/frame_depth.png`.
+pub fn with_suffix(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}{}", stem, suffix)
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// Saves the pixels as a binary PPM image
+fn save_ppm(path: &str, width: usize, height: usize, pixels: &[u8]) -> Result<(), String> {
+    let mut file = fs::File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    write!(file, "P6\n{} {}\n255\n", width, height)
+        .and_then(|_| file.write_all(pixels))
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth_image() {
+        let mut framebuffer = Framebuffer::new(3, 1);
+        // Depth of points at distances 1 and 10, with near 1 and far 100
+        let depth_at = |z: f32| 100.0 / 99.0 - 100.0 / (99.0 * z);
+        framebuffer.depth = vec![depth_at(1.0), depth_at(10.0), f32::INFINITY];
+
+        let linear = framebuffer.linear_depth(1.0, 100.0);
+        assert!((linear[0] - 1.0).abs() < 1e-4);
+        assert!((linear[1] - 10.0).abs() < 1e-3);
+        assert_eq!(framebuffer.depth_image(1.0, 100.0), vec![255, 255, 255, 32, 32, 32, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_blend() {
+        let mut framebuffer = Framebuffer::new(1, 1);
+        framebuffer.clear(Color::RGB(0, 100, 255));
+        framebuffer.blend(0, 0, Color::RGB(255, 0, 255), 0.25);
+        assert_eq!(framebuffer.get(0, 0), Color::RGB(64, 75, 255));
+    }
+
+    #[test]
+    fn test_render_size() {
+        assert_eq!(render_size(800, 600, 1.0), (800, 600));
+        assert_eq!(render_size(801, 600, 0.5), (401, 300));
+        assert_eq!(render_size(1, 1, 0.25), (1, 1));
+    }
+
+    #[test]
+    fn test_with_suffix() {
+        assert_eq!(with_suffix("shots/frame.png", "_depth"), "shots/frame_depth.png");
+        assert_eq!(with_suffix("frame", "_depth"), "frame_depth");
+    }
+}
@@ -0,0 +1,128 @@
+/*!
+# frustum.rs
+
+View-frustum culling.
+
+The six planes of the frustum are extracted from a view-projection
+matrix (Gribb & Hartmann), so they live in the space the matrix
+transforms from. Anything fully outside one of the planes can't be
+seen and is skipped before doing any more work on it.
+*/
+
+use na::{Matrix4, Vector3, Vector4};
+
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::polygons::Tri;
+
+/// A plane given by `normal . p + d = 0`, with the normal pointing inside
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub d: f32
+}
+
+/// The 6 planes enclosing everything the camera can see:
+/// left, right, bottom, top, near and far
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Plane; 6]
+}
+
+impl Plane {
+    /// Creates a normalized plane from its `(a, b, c, d)` coefficients
+    fn from_coefficients(v: Vector4<f32>) -> Self {
+        let normal = v.xyz();
+        let length = normal.magnitude();
+        Self {
+            normal: normal / length,
+            d: v.w / length
+        }
+    }
+
+    /// Signed distance from the plane to the point, positive on the inside
+    pub fn distance(&self, point: &Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+impl Frustum {
+    /// Extracts the frustum planes from a view-projection matrix
+    ///
+    /// The projection maps the depth to `[0, 1]`, so the near plane
+    /// is `z >= 0` instead of `z >= -w`.
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let row = |i: usize| m.row(i).transpose();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [
+                Plane::from_coefficients(r3 + r0),
+                Plane::from_coefficients(r3 - r0),
+                Plane::from_coefficients(r3 + r1),
+                Plane::from_coefficients(r3 - r1),
+                Plane::from_coefficients(r2),
+                Plane::from_coefficients(r3 - r2)
+            ]
+        }
+    }
+
+    /// Whether any part of the sphere may be inside the frustum
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.distance(&sphere.center) >= -sphere.radius)
+    }
+
+    /// Whether any part of the box may be inside the frustum
+    ///
+    /// Only the corner furthest along each plane normal is tested.
+    #[allow(dead_code)]
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let furthest = Vector3::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z }
+            );
+            plane.distance(&furthest) >= 0.0
+        })
+    }
+
+    /// Whether the triangle may be inside the frustum
+    ///
+    /// A triangle is only rejected when all of its vertices are
+    /// outside the same plane.
+    pub fn intersects_tri(&self, tri: &Tri) -> bool {
+        self.planes.iter().all(|plane| {
+            tri.p.iter().any(|p| plane.distance(&p.xyz()) >= 0.0)
+        })
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    #[test]
+    fn test_frustum() {
+        let camera = Camera::new(Vector3::zeros(), Vector3::new(0.0, 0.0, -1.0));
+        let frustum = Frustum::from_matrix(&(camera.projection_matrix(1.0) * camera.view_matrix()));
+
+        let ahead = BoundingSphere::new(Vector3::new(0.0, 0.0, -10.0), 1.0);
+        let behind = BoundingSphere::new(Vector3::new(0.0, 0.0, 10.0), 1.0);
+        let aside = BoundingSphere::new(Vector3::new(20.0, 0.0, -10.0), 1.0);
+        assert!(frustum.intersects_sphere(&ahead));
+        assert!(!frustum.intersects_sphere(&behind));
+        assert!(!frustum.intersects_sphere(&aside));
+
+        let mut aabb = Aabb::empty();
+        aabb.add_point(Vector3::new(-1.0, -1.0, -2000.0));
+        aabb.add_point(Vector3::new(1.0, 1.0, -1500.0));
+        assert!(!frustum.intersects_aabb(&aabb));
+
+        let tri = Tri::from([-1.0, 0.0, -5.0, 1.0, 0.0, -5.0, 0.0, 1.0, -5.0]);
+        let far_tri = Tri::from([-1.0, 0.0, 5.0, 1.0, 0.0, 5.0, 0.0, 1.0, 5.0]);
+        assert!(frustum.intersects_tri(&tri));
+        assert!(!frustum.intersects_tri(&far_tri));
+    }
+}
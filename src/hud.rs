@@ -0,0 +1,150 @@
+/*!
+# hud.rs
+
+On-screen overlay with the frame rate, where the frame time goes, what
+happened to the triangles and the current render settings, drawn with
+SDL_ttf over the rendered frame.
+*/
+
+use std::time::Duration;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, TextureCreator};
+use sdl2::ttf::{Font, Sdl2TtfContext};
+use sdl2::video::{Window, WindowContext};
+
+use crate::camera::Camera;
+use crate::render::{RenderSettings, RenderStats};
+
+/// Weight of the newest frame in the averaged frame times
+const SMOOTHING: f32 = 0.1;
+
+/// Frame times averaged over the last frames, in seconds, so the
+/// numbers are readable
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimes {
+    pub frame: f32,
+    pub geometry: f32,
+    pub raster: f32,
+    pub present: f32
+}
+
+impl FrameTimes {
+    /// Adds the times of a frame to the averages
+    pub fn add(&mut self, frame: Duration, stats: &RenderStats, present: Duration) {
+        let times = [frame, stats.geometry_time, stats.raster_time, present];
+        let averages = [&mut self.frame, &mut self.geometry, &mut self.raster, &mut self.present];
+        for (average, time) in averages.into_iter().zip(times) {
+            *average = if *average == 0.0 {
+                time.as_secs_f32()
+            } else {
+                *average + (time.as_secs_f32() - *average) * SMOOTHING
+            };
+        }
+    }
+}
+
+/// The overlay and its font
+pub struct Hud<'ttf> {
+    font: Font<'ttf, 'static>,
+    pub visible: bool
+}
+
+impl<'ttf> Hud<'ttf> {
+    /// Loads the font of the overlay
+    pub fn new(ttf_context: &'ttf Sdl2TtfContext, font_path: &str, visible: bool) -> Result<Self, String> {
+        let font = ttf_context.load_font(font_path, 14).map_err(|e| format!("{}: {}", font_path, e))?;
+        Ok(Self { font, visible })
+    }
+
+    /// Draws the lines in the top left corner of the canvas
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+        lines: &[String]
+    ) -> Result<(), String> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let line_height = self.font.recommended_line_spacing();
+        let width = lines.iter()
+            .map(|line| self.font.size_of(line).map(|(w, _)| w))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+
+        // Darken the frame behind the text so it stays readable
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        canvas.fill_rect(Rect::new(4, 4, width + 8, (line_height * lines.len() as i32 + 8) as u32))?;
+        canvas.set_blend_mode(BlendMode::None);
+
+        for (i, line) in lines.iter().enumerate() {
+            // Empty lines can't be rendered, but still take their space
+            if line.is_empty() {
+                continue;
+            }
+            let surface = self.font.render(line).blended(Color::WHITE).map_err(|e| e.to_string())?;
+            let texture = texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?;
+            let target = Rect::new(8, 8 + line_height * i as i32, surface.width(), surface.height());
+            canvas.copy(&texture, None, target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Text of the overlay
+pub fn lines(times: &FrameTimes, stats: &RenderStats, camera: &Camera, settings: &RenderSettings) -> Vec<String> {
+    let ms = |seconds: f32| seconds * 1000.0;
+    vec![
+        format!("{:.1} fps, {:.2} ms", 1.0 / times.frame.max(f32::EPSILON), ms(times.frame)),
+        format!(
+            "geometry {:.2} ms, raster {:.2} ms, present {:.2} ms",
+            ms(times.geometry), ms(times.raster), ms(times.present)
+        ),
+        format!("objects: {} loaded, {} culled, {} threads", stats.objects, stats.objects_culled, settings.threads),
+        format!(
+            "triangles: {} loaded, {} culled, {} face culled, {} clipped, {} drawn, {} transparent",
+            stats.triangles, stats.triangles_culled, stats.triangles_face_culled,
+            stats.triangles_clipped, stats.triangles_drawn, stats.triangles_transparent
+        ),
+        format!(
+            "camera: ({:.2}, {:.2}, {:.2}), fov {:.0}",
+            camera.position.x, camera.position.y, camera.position.z, camera.fov
+        ),
+        format!(
+            "mode {:?}, shading {:?}, shadows {}, normal maps {}, cull {:?}, front face {:?}, triangle culling {}",
+            settings.mode, settings.shading, if settings.shadows { "on" } else { "off" },
+            if settings.normal_mapping { "on" } else { "off" },
+            settings.cull_mode, settings.front_face,
+            if settings.triangle_culling { "on" } else { "off" }
+        ),
+        format!(
+            "debug view {:?}, normals {}, antialiasing {:?}, transparency {:?}, texture filter {:?}",
+            settings.debug, if settings.show_normals { "on" } else { "off" }, settings.antialiasing,
+            settings.transparency, settings.texture_filter
+        )
+    ]
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_times() {
+        let mut times = FrameTimes::default();
+        let stats = RenderStats::default();
+        // The first frame sets the averages, the next ones move them slowly
+        times.add(Duration::from_millis(20), &stats, Duration::ZERO);
+        assert!((times.frame - 0.020).abs() < 1e-6);
+        times.add(Duration::from_millis(10), &stats, Duration::ZERO);
+        assert!((times.frame - 0.019).abs() < 1e-6);
+    }
+}
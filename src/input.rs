@@ -0,0 +1,550 @@
+/*!
+# input.rs
+
+Input layer between SDL events and what they do in the viewer.
+
+Keys and game controller buttons are bound to named actions, like
+`move_forward` or `screenshot`. Movement actions last while their key
+is held, the others are triggered once per key press. The sticks and
+triggers of game controllers move and turn the camera too. The default
+bindings can be changed from a TOML file:
+
+```toml
+[bindings]
+move_forward = ["W", "Keypad 8"]
+screenshot = ["F12"]
+
+[controller]
+screenshot = ["leftshoulder", "rightstick"]
+```
+
+Keys are named like SDL does (`"Left Shift"`, `"Space"`, `"F1"`), and so
+are buttons (`"a"`, `"start"`, `"dpup"`). A key or button bound in the
+file is taken away from the action it had by default.
+*/
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+
+use na::{Vector2, Vector3};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::GameControllerSubsystem;
+use serde::Deserialize;
+
+/// Something the user can ask for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    LookLeft,
+    LookRight,
+    LookUp,
+    LookDown,
+    FrameScene,
+    CycleRenderMode,
+    CycleDebugView,
+    ToggleNormals,
+    CycleAntialiasing,
+    CycleTransparency,
+    CycleTextureFilter,
+    ToggleShadows,
+    ToggleNormalMaps,
+    ToggleTriangleCulling,
+    CycleCullMode,
+    FlipFrontFace,
+    Screenshot,
+    ToggleRecording,
+    ToggleHud,
+    ShowBindings,
+    ToggleFullscreen,
+    Quit
+}
+
+impl Action {
+    /// Every action, in the order the bindings are listed
+    pub const ALL: [Action; 28] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::LookLeft,
+        Action::LookRight,
+        Action::LookUp,
+        Action::LookDown,
+        Action::FrameScene,
+        Action::CycleRenderMode,
+        Action::CycleDebugView,
+        Action::ToggleNormals,
+        Action::CycleAntialiasing,
+        Action::CycleTransparency,
+        Action::CycleTextureFilter,
+        Action::ToggleShadows,
+        Action::ToggleNormalMaps,
+        Action::ToggleTriangleCulling,
+        Action::CycleCullMode,
+        Action::FlipFrontFace,
+        Action::Screenshot,
+        Action::ToggleRecording,
+        Action::ToggleHud,
+        Action::ShowBindings,
+        Action::ToggleFullscreen,
+        Action::Quit
+    ];
+
+    /// Name of the action in the bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::LookLeft => "look_left",
+            Action::LookRight => "look_right",
+            Action::LookUp => "look_up",
+            Action::LookDown => "look_down",
+            Action::FrameScene => "frame_scene",
+            Action::CycleRenderMode => "cycle_render_mode",
+            Action::CycleDebugView => "cycle_debug_view",
+            Action::ToggleNormals => "toggle_normals",
+            Action::CycleAntialiasing => "cycle_antialiasing",
+            Action::CycleTransparency => "cycle_transparency",
+            Action::CycleTextureFilter => "cycle_texture_filter",
+            Action::ToggleShadows => "toggle_shadows",
+            Action::ToggleNormalMaps => "toggle_normal_maps",
+            Action::ToggleTriangleCulling => "toggle_triangle_culling",
+            Action::CycleCullMode => "cycle_cull_mode",
+            Action::FlipFrontFace => "flip_front_face",
+            Action::Screenshot => "screenshot",
+            Action::ToggleRecording => "toggle_recording",
+            Action::ToggleHud => "toggle_hud",
+            Action::ShowBindings => "show_bindings",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+            Action::Quit => "quit"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|action| action.name() == name)
+    }
+
+    /// Whether the action lasts while its key is held, instead of
+    /// happening once when it is pressed
+    pub fn is_continuous(self) -> bool {
+        matches!(
+            self,
+            Action::MoveForward | Action::MoveBackward | Action::MoveLeft |
+            Action::MoveRight | Action::MoveUp | Action::MoveDown |
+            Action::LookLeft | Action::LookRight | Action::LookUp | Action::LookDown
+        )
+    }
+
+    /// Keys bound to the action by default
+    fn default_keys(self) -> Vec<Keycode> {
+        vec![match self {
+            Action::MoveForward => Keycode::W,
+            Action::MoveBackward => Keycode::S,
+            Action::MoveLeft => Keycode::A,
+            Action::MoveRight => Keycode::D,
+            Action::MoveUp => Keycode::Space,
+            Action::MoveDown => Keycode::LShift,
+            Action::LookLeft => Keycode::Left,
+            Action::LookRight => Keycode::Right,
+            Action::LookUp => Keycode::Up,
+            Action::LookDown => Keycode::Down,
+            Action::FrameScene => Keycode::F,
+            Action::CycleRenderMode => Keycode::M,
+            Action::CycleDebugView => Keycode::X,
+            Action::ToggleNormals => Keycode::N,
+            Action::CycleAntialiasing => Keycode::L,
+            Action::CycleTransparency => Keycode::O,
+            Action::CycleTextureFilter => Keycode::K,
+            Action::ToggleShadows => Keycode::T,
+            Action::ToggleNormalMaps => Keycode::G,
+            Action::ToggleTriangleCulling => Keycode::C,
+            Action::CycleCullMode => Keycode::B,
+            Action::FlipFrontFace => Keycode::V,
+            Action::Screenshot => Keycode::P,
+            Action::ToggleRecording => Keycode::R,
+            Action::ToggleHud => Keycode::H,
+            Action::ShowBindings => Keycode::F1,
+            Action::ToggleFullscreen => Keycode::F11,
+            Action::Quit => Keycode::Escape
+        }]
+    }
+
+    /// Controller buttons bound to the action by default, movement
+    /// and looking around are on the sticks and triggers
+    fn default_buttons(self) -> Vec<Button> {
+        match self {
+            Action::FrameScene => vec![Button::A],
+            Action::CycleRenderMode => vec![Button::LeftStick],
+            Action::CycleDebugView => vec![Button::RightStick],
+            Action::FlipFrontFace => vec![Button::B],
+            Action::ToggleTriangleCulling => vec![Button::X],
+            Action::CycleCullMode => vec![Button::Y],
+            Action::Screenshot => vec![Button::LeftShoulder],
+            Action::ToggleRecording => vec![Button::RightShoulder],
+            Action::ToggleHud => vec![Button::Start],
+            Action::ShowBindings => vec![Button::Back],
+            _ => Vec::new()
+        }
+    }
+}
+
+/// Sticks inside this radius count as centered, they rarely rest at exactly 0
+const DEAD_ZONE: f32 = 0.2;
+
+/// Keys and controller buttons bound to each action
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    keys: Vec<(Action, Vec<Keycode>)>,
+    buttons: Vec<(Action, Vec<Button>)>
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: Action::ALL.iter().map(|&action| (action, action.default_keys())).collect(),
+            buttons: Action::ALL.iter().map(|&action| (action, action.default_buttons())).collect()
+        }
+    }
+}
+
+/// Layout of the bindings file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingsFile {
+    #[serde(default)]
+    bindings: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    controller: BTreeMap<String, Vec<String>>
+}
+
+impl Bindings {
+    /// Loads the bindings file, on top of the default bindings
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::parse(&contents).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Parses the contents of a bindings file
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let file: BindingsFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let mut bindings = Self::default();
+        for (name, key_names) in file.bindings.iter() {
+            let action = Action::from_name(name).ok_or_else(|| format!("unknown action \"{}\"", name))?;
+            let keys = key_names.iter()
+                .map(|key| Keycode::from_name(key).ok_or_else(|| format!("unknown key \"{}\" for {}", key, name)))
+                .collect::<Result<Vec<_>, _>>()?;
+            bind(&mut bindings.keys, action, keys);
+        }
+        for (name, button_names) in file.controller.iter() {
+            let action = Action::from_name(name).ok_or_else(|| format!("unknown action \"{}\"", name))?;
+            let buttons = button_names.iter()
+                .map(|button| Button::from_string(button).ok_or_else(|| format!("unknown button \"{}\" for {}", button, name)))
+                .collect::<Result<Vec<_>, _>>()?;
+            bind(&mut bindings.buttons, action, buttons);
+        }
+        Ok(bindings)
+    }
+
+    /// Action the key is bound to
+    pub fn key_action(&self, key: Keycode) -> Option<Action> {
+        bound_action(&self.keys, &key)
+    }
+
+    /// Action the controller button is bound to
+    pub fn button_action(&self, button: Button) -> Option<Action> {
+        bound_action(&self.buttons, &button)
+    }
+
+    /// Keys bound to the action
+    pub fn keys(&self, action: Action) -> &[Keycode] {
+        bound_inputs(&self.keys, action)
+    }
+
+    /// Controller buttons bound to the action
+    pub fn buttons(&self, action: Action) -> &[Button] {
+        bound_inputs(&self.buttons, action)
+    }
+
+    /// One line per action with its keys and buttons, for the overlay
+    pub fn describe(&self) -> Vec<String> {
+        let list = |names: Vec<String>| if names.is_empty() { "-".to_string() } else { names.join(", ") };
+        let mut lines = vec![format!("{:<24} {:<24} {}", "action", "keys", "controller")];
+        lines.extend(Action::ALL.iter().map(|&action| format!(
+            "{:<24} {:<24} {}",
+            action.name(),
+            list(self.keys(action).iter().map(|key| key.name()).collect()),
+            list(self.buttons(action).iter().map(|button| button.string()).collect())
+        )));
+        lines.push("left stick: move, right stick: look, triggers: down / up".to_string());
+        lines
+    }
+}
+
+/// Binds the inputs to the action, replacing the ones it had and
+/// taking them away from other actions
+fn bind<T: PartialEq>(bindings: &mut [(Action, Vec<T>)], action: Action, inputs: Vec<T>) {
+    for (_, bound) in bindings.iter_mut() {
+        bound.retain(|input| !inputs.contains(input));
+    }
+    if let Some((_, bound)) = bindings.iter_mut().find(|(a, _)| *a == action) {
+        *bound = inputs;
+    }
+}
+
+fn bound_action<T: PartialEq>(bindings: &[(Action, Vec<T>)], input: &T) -> Option<Action> {
+    bindings.iter()
+        .find(|(_, inputs)| inputs.contains(input))
+        .map(|(action, _)| *action)
+}
+
+fn bound_inputs<T>(bindings: &[(Action, Vec<T>)], action: Action) -> &[T] {
+    bindings.iter()
+        .find(|(a, _)| *a == action)
+        .map_or(&[], |(_, inputs)| inputs)
+}
+
+/// State of the input, fed with the SDL events of each frame
+pub struct Input {
+    pub bindings: Bindings,
+    /// Keys held down
+    held_keys: HashSet<Keycode>,
+    /// Controller buttons held down, with the instance id of their controller
+    held_buttons: HashSet<(u32, Button)>,
+    /// Position of the sticks and triggers of each controller by instance id,
+    /// from -1 to 1, indexed like `AXES`
+    axes: HashMap<u32, [f32; 6]>,
+    /// Actions triggered since they were last taken
+    triggered: Vec<Action>
+}
+
+/// Controller axes, in the order `Input` keeps them
+const AXES: [Axis; 6] = [Axis::LeftX, Axis::LeftY, Axis::RightX, Axis::RightY, Axis::TriggerLeft, Axis::TriggerRight];
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            held_keys: HashSet::new(),
+            held_buttons: HashSet::new(),
+            axes: HashMap::new(),
+            triggered: Vec::new()
+        }
+    }
+
+    /// Updates the state with an event
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::KeyDown { keycode: Some(key), repeat, .. } => {
+                self.held_keys.insert(*key);
+                match self.bindings.key_action(*key) {
+                    Some(action) if !action.is_continuous() && !repeat => self.triggered.push(action),
+                    _ => {}
+                }
+            },
+            Event::KeyUp { keycode: Some(key), .. } => {
+                self.held_keys.remove(key);
+            },
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.held_buttons.insert((*which, *button));
+                match self.bindings.button_action(*button) {
+                    Some(action) if !action.is_continuous() => self.triggered.push(action),
+                    _ => {}
+                }
+            },
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.held_buttons.remove(&(*which, *button));
+            },
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                if let Some(i) = AXES.iter().position(|a| a == axis) {
+                    self.axes.entry(*which).or_insert([0.0; 6])[i] = (*value as f32 / i16::MAX as f32).max(-1.0);
+                }
+            },
+            // Don't keep moving with the state of an unplugged controller
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.held_buttons.retain(|(id, _)| id != which);
+                self.axes.remove(which);
+            },
+            Event::Quit { .. } => self.triggered.push(Action::Quit),
+            _ => {}
+        }
+    }
+
+    /// Actions triggered since the last call, in order
+    pub fn take_triggered(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.triggered)
+    }
+
+    /// Whether a key or button bound to the action is held down
+    pub fn is_held(&self, action: Action) -> bool {
+        self.bindings.keys(action).iter().any(|key| self.held_keys.contains(key)) ||
+            self.held_buttons.iter().any(|(_, button)| self.bindings.buttons(action).contains(button))
+    }
+
+    /// Position of a controller stick, with the dead zone taken out
+    /// and y pointing up
+    ///
+    /// With several controllers, the stick pushed the furthest wins.
+    fn stick(&self, x: Axis, y: Axis) -> Vector2<f32> {
+        let index = |axis| AXES.iter().position(|&a| a == axis).unwrap();
+        let stick = self.axes.values()
+            .map(|axes| Vector2::new(axes[index(x)], -axes[index(y)]))
+            .max_by(|a, b| a.magnitude().total_cmp(&b.magnitude()))
+            .unwrap_or_else(Vector2::zeros);
+        let length = stick.magnitude();
+        if length < DEAD_ZONE {
+            return Vector2::zeros();
+        }
+        // Start from 0 at the edge of the dead zone, so small movements stay small
+        stick / length * ((length - DEAD_ZONE) / (1.0 - DEAD_ZONE)).min(1.0)
+    }
+
+    /// Value of the pair of held actions, from -1 to 1
+    fn held_axis(&self, positive: Action, negative: Action) -> f32 {
+        (self.is_held(positive) as i32 - self.is_held(negative) as i32) as f32
+    }
+
+    /// Direction the user wants to move in, relative to the camera:
+    /// x is right, y is up and z is forward
+    ///
+    /// Each component is between -1 and 1, controllers can give anything in between.
+    pub fn movement(&self) -> Vector3<f32> {
+        let stick = self.stick(Axis::LeftX, Axis::LeftY);
+        let triggers = self.axes.values().map(|axes| axes[5] - axes[4])
+            .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+            .unwrap_or(0.0);
+        let movement = Vector3::new(
+            self.held_axis(Action::MoveRight, Action::MoveLeft) + stick.x,
+            self.held_axis(Action::MoveUp, Action::MoveDown) + triggers,
+            self.held_axis(Action::MoveForward, Action::MoveBackward) + stick.y
+        );
+        movement.map(|c| c.clamp(-1.0, 1.0))
+    }
+
+    /// How fast the user wants to turn: x to the right and y up, from -1 to 1
+    pub fn look(&self) -> Vector2<f32> {
+        let stick = self.stick(Axis::RightX, Axis::RightY);
+        let look = Vector2::new(
+            self.held_axis(Action::LookRight, Action::LookLeft) + stick.x,
+            self.held_axis(Action::LookUp, Action::LookDown) + stick.y
+        );
+        look.map(|c| c.clamp(-1.0, 1.0))
+    }
+}
+
+/// Game controllers plugged in, they only send events while open
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: Vec<GameController>
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem) -> Self {
+        Self { subsystem, open: Vec::new() }
+    }
+
+    /// Opens controllers as they are plugged in, and closes them when unplugged
+    ///
+    /// Controllers plugged in at startup are announced with an event too.
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(*which) {
+                Ok(controller) => {
+                    println!("Controller connected: {}", controller.name());
+                    self.open.push(controller);
+                },
+                Err(error) => eprintln!("Error opening controller {}: {}", which, error)
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.open.retain(|controller| controller.instance_id() != *which);
+            },
+            _ => {}
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_down(key: Keycode, repeat: bool) -> Event {
+        Event::KeyDown { timestamp: 0, window_id: 0, keycode: Some(key), scancode: None, keymod: sdl2::keyboard::Mod::NOMOD, repeat }
+    }
+
+    #[test]
+    fn test_bindings() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+
+        // Rebinding a key takes it from the action it had
+        let mut bindings = Bindings::default();
+        bind(&mut bindings.keys, Action::Screenshot, vec![Keycode::F, Keycode::F12]);
+        assert_eq!(bindings.key_action(Keycode::F), Some(Action::Screenshot));
+        assert_eq!(bindings.keys(Action::FrameScene), &[]);
+        assert_eq!(bindings.key_action(Keycode::P), None);
+        assert_eq!(bindings.button_action(Button::A), Some(Action::FrameScene));
+
+        assert!(Bindings::parse("[bindings]\njump = []").is_err());
+        assert!(Bindings::parse("[keys]").is_err());
+    }
+
+    #[test]
+    fn test_input() {
+        let mut input = Input::new(Bindings::default());
+        input.handle_event(&key_down(Keycode::W, false));
+        input.handle_event(&key_down(Keycode::D, false));
+        input.handle_event(&key_down(Keycode::F, false));
+        input.handle_event(&key_down(Keycode::F, true));
+        assert_eq!(input.movement(), Vector3::new(1.0, 0.0, 1.0));
+        assert_eq!(input.take_triggered(), vec![Action::FrameScene]);
+        assert!(input.take_triggered().is_empty());
+
+        input.handle_event(&Event::KeyUp {
+            timestamp: 0, window_id: 0, keycode: Some(Keycode::W), scancode: None, keymod: sdl2::keyboard::Mod::NOMOD, repeat: false
+        });
+        assert_eq!(input.movement(), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_controller() {
+        let mut input = Input::new(Bindings::default());
+        let axis = |axis, value| Event::ControllerAxisMotion { timestamp: 0, which: 0, axis, value };
+
+        // Resting sticks are in the dead zone
+        input.handle_event(&axis(Axis::LeftX, 3000));
+        assert_eq!(input.movement(), Vector3::zeros());
+
+        // Left stick pushed all the way up moves forward, the right trigger moves up
+        input.handle_event(&axis(Axis::LeftY, i16::MIN));
+        input.handle_event(&axis(Axis::TriggerRight, i16::MAX));
+        input.handle_event(&axis(Axis::RightX, i16::MAX / 2));
+        let movement = input.movement();
+        assert!((movement.z - 1.0).abs() < 1e-2 && movement.y == 1.0);
+        assert!(input.look().x > 0.0 && input.look().x < 0.5);
+
+        input.handle_event(&Event::ControllerButtonDown { timestamp: 0, which: 0, button: Button::Y });
+        assert_eq!(input.take_triggered(), vec![Action::CycleCullMode]);
+
+        // Unplugging another controller keeps the input of this one
+        input.handle_event(&Event::ControllerButtonDown { timestamp: 0, which: 1, button: Button::A });
+        input.handle_event(&Event::ControllerDeviceRemoved { timestamp: 0, which: 1 });
+        assert!((input.movement().z - 1.0).abs() < 1e-2);
+
+        input.handle_event(&Event::ControllerDeviceRemoved { timestamp: 0, which: 0 });
+        assert_eq!(input.movement(), Vector3::zeros());
+    }
+}
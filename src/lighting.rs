@@ -0,0 +1,160 @@
+/*!
+# lighting.rs
+
+Lights in the scene and the shading of surfaces lit by them.
+
+Directional lights light everything from the same direction, and spot
+lights a cone from their position. Both can cast shadows, see `shadow`.
+*/
+
+use na::Vector3;
+use sdl2::pixels::Color;
+
+/// How faces are colored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shading {
+    /// One color per face, lit by the lights of the scene
+    Flat,
+    /// The normals of the vertices interpolated across the face, lit per pixel
+    Phong,
+    /// The color of the material, ignoring the lights
+    Unlit
+}
+
+/// Fraction of the angle of the cone of spot lights over which they fade
+/// out towards its edge
+const SPOT_FADE: f32 = 0.2;
+
+/// Where the light comes from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away, lighting everything from the same direction
+    Directional,
+    /// At a position, lighting a cone around its direction
+    Spot {
+        position: Vector3<f32>,
+        /// Angle between the axis and the edge of the cone, in degrees
+        angle: f32
+    }
+}
+
+/// A light in the scene
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Direction the light travels in (normalized), along the axis of
+    /// the cone for spot lights
+    pub direction: Vector3<f32>,
+    pub color: Color,
+    pub intensity: f32,
+    /// Whether objects block the light, casting shadows
+    pub shadows: bool
+}
+
+impl Light {
+    /// Creates a white light travelling in the given direction
+    pub fn directional(direction: Vector3<f32>) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            direction: direction.normalize(),
+            color: Color::WHITE,
+            intensity: 1.0,
+            shadows: true
+        }
+    }
+
+    /// Creates a white light at the position, shining a cone `angle`
+    /// degrees wide on each side of the direction
+    pub fn spot(position: Vector3<f32>, direction: Vector3<f32>, angle: f32) -> Self {
+        Self {
+            kind: LightKind::Spot { position, angle },
+            ..Self::directional(direction)
+        }
+    }
+
+    /// Direction the light travels in when it reaches the point
+    pub fn direction_at(&self, point: &Vector3<f32>) -> Vector3<f32> {
+        match self.kind {
+            LightKind::Directional => self.direction,
+            LightKind::Spot { position, .. } => (point - position).try_normalize(f32::EPSILON).unwrap_or(self.direction)
+        }
+    }
+
+    /// Fraction of the light reaching the point, fading out towards the
+    /// edge of the cone of spot lights
+    pub fn falloff(&self, point: &Vector3<f32>) -> f32 {
+        match self.kind {
+            LightKind::Directional => 1.0,
+            LightKind::Spot { angle, .. } => {
+                let cos = self.direction_at(point).dot(&self.direction);
+                let (outer, inner) = (angle.to_radians().cos(), (angle * (1.0 - SPOT_FADE)).to_radians().cos());
+                ((cos - outer) / (inner - outer)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+/// Color of a surface with the given normal and base color at a point,
+/// lit by the lights
+///
+/// Uses wrapped diffuse lighting, so faces pointing away from a light
+/// fade into black instead of cutting off at the terminator.
+/// `visibility` gives the fraction of each light, by its index, that
+/// isn't blocked on its way to the point.
+pub fn shade<V: Fn(usize) -> f32>(
+    normal: &Vector3<f32>,
+    point: &Vector3<f32>,
+    lights: &[Light],
+    base: Color,
+    visibility: V
+) -> Color {
+    let mut rgb = Vector3::new(0.0, 0.0, 0.0);
+    for (i, light) in lights.iter().enumerate() {
+        let mut diffuse = (1.0 - normal.dot(&light.direction_at(point))) * 0.5 * light.intensity * light.falloff(point);
+        if diffuse <= 0.0 {
+            continue;
+        }
+        diffuse *= visibility(i);
+        rgb += Vector3::new(
+            light.color.r as f32,
+            light.color.g as f32,
+            light.color.b as f32
+        ) / 255.0 * diffuse;
+    }
+    Color::RGB(
+        (base.r as f32 * rgb.x).min(255.0) as u8,
+        (base.g as f32 * rgb.y).min(255.0) as u8,
+        (base.b as f32 * rgb.z).min(255.0) as u8
+    )
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shade() {
+        let lights = [Light::directional(Vector3::new(0.0, -1.0, 0.0))];
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let origin = Vector3::zeros();
+
+        assert_eq!(shade(&up, &origin, &lights, Color::WHITE, |_| 1.0), Color::WHITE);
+        assert_eq!(shade(&-up, &origin, &lights, Color::WHITE, |_| 1.0), Color::BLACK);
+        assert_eq!(shade(&up, &origin, &[], Color::WHITE, |_| 1.0), Color::BLACK);
+        assert_eq!(shade(&up, &origin, &lights, Color::WHITE, |_| 0.5), Color::RGB(127, 127, 127));
+    }
+
+    #[test]
+    fn test_spot() {
+        // A spot light 1 unit above the ground, 45 degrees wide
+        let light = Light::spot(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0), 45.0);
+        assert_eq!(light.falloff(&Vector3::zeros()), 1.0);
+        assert_eq!(light.falloff(&Vector3::new(2.0, 0.0, 0.0)), 0.0);
+        let edge = light.falloff(&Vector3::new(0.9, 0.0, 0.0));
+        assert!(edge > 0.0 && edge < 1.0);
+
+        let to_side = light.direction_at(&Vector3::new(1.0, 0.0, 0.0));
+        assert!((to_side - Vector3::new(1.0, -1.0, 0.0).normalize()).magnitude() < 1e-6);
+    }
+}
@@ -54,6 +54,7 @@ pub fn rotation_matrix_z(angle: f32) -> Matrix4<f32> {
 /// Translation matrix
 ///
 /// Output is a 4x4 translation matrix for a given vector.
+#[allow(dead_code)]
 pub fn translation_matrix(x: f32, y: f32, z: f32) -> Matrix4<f32> {
     Matrix4::new(
         1.0, 0.0, 0.0, x,
@@ -80,18 +81,37 @@ pub fn projection_matrix(fov: f32, aspect_ratio: f32, near: f32, far: f32) -> Ma
     )
 }
 
+/// Orthographic projection matrix
+///
+/// * Output is a 4x4 projection matrix without perspective
+///
+/// The view from `-half_width` to `half_width` and from `-half_height`
+/// to `half_height` is mapped to the screen, and the depth between the
+/// near and far planes to `[0, 1]` like `projection_matrix` does.
+pub fn orthographic_matrix(half_width: f32, half_height: f32, near: f32, far: f32) -> Matrix4<f32> {
+    Matrix4::new(
+        1.0 / half_width, 0.0, 0.0, 0.0,
+        0.0, 1.0 / half_height, 0.0, 0.0,
+        0.0, 0.0, 1.0 / (far - near), -near / (far - near),
+        0.0, 0.0, 0.0, 1.0
+    )
+}
+
 /// View matrix
 ///
-/// Output is a 4x4 view matrix for a given position and target. (It is broken)
+/// Output is a 4x4 view matrix for a given position and view direction.
+///
+/// The view is a rigid transform, so the view space stays right handed:
+/// +z points along the view direction, +y up and +x to the camera's left.
 pub fn view_matrix(camera: Vector3<f32>, view_direction: Vector3<f32>, up: Vector3<f32>) -> Matrix4<f32> {
     let z = (view_direction).normalize();
     let x = up.cross(&z).normalize();
     let y = z.cross(&x).normalize();
 
     Matrix4::new(
-        x.x, y.x, z.x, 0.0,
-        x.y, y.y, z.y, 0.0,
-        x.z, y.z, z.z, 0.0,
-        x.dot(&-camera), y.dot(&-camera), z.dot(&-camera), 1.0
+        x.x, x.y, x.z, x.dot(&-camera),
+        y.x, y.y, y.z, y.dot(&-camera),
+        z.x, z.y, z.z, z.dot(&-camera),
+        0.0, 0.0, 0.0, 1.0
     )
-}
\ No newline at end of file
+}
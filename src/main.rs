@@ -12,233 +12,499 @@ This is a work in progress, used mostly for me to understand the
 aplications of linear algebra in computer graphics.
 */
 
-use std::time::{SystemTime, Duration};
+use std::time::{Duration, Instant};
 
 extern crate nalgebra as na;
 use na::{Vector3};//, U3, U4, DefaultAllocator, allocator::Allocator};
 
 mod polygons;
-use polygons::Mesh;
 mod linear_transforms;
+mod bounds;
+mod camera;
+use camera::Camera;
+mod frustum;
+mod culling;
+mod transform;
+mod scene;
+use scene::Scene;
+mod scene_file;
+use scene_file::SceneFile;
+mod material;
+mod mtl;
+mod lighting;
+mod cli;
+use cli::{Command, Options};
+mod framebuffer;
+use framebuffer::Framebuffer;
+mod raster;
+mod antialiasing;
+mod transparency;
+mod shadow;
+mod texture;
+mod parallel;
+mod simd;
+mod render;
+use render::{Projected, RenderMode, RenderSettings};
+mod offline;
+use offline::OfflineRender;
+mod screenshot;
+mod recording;
+use recording::Recorder;
+mod hud;
+use hud::{FrameTimes, Hud};
+mod clock;
+use clock::{Clock, FrameLimiter};
+mod input;
+use input::{Action, Bindings, Controllers, Input};
 
-use sdl2;
 use sdl2::{
-    pixels::Color,
-    event::Event,
+    pixels::{Color, PixelFormatEnum},
+    event::{Event, WindowEvent},
+    video::FullscreenType,
     video::Window,
     render::Canvas,
     EventPump,
-    keyboard::Keycode,
     rect::Rect,
 };
 
 
-// Window size
-const WIDTH: usize = 800;
-const HEIGHT: usize = 600;
-const FPS_CAP: u64 = 60;
-const BG_COLOR: Color = Color::RGB(15, 17, 17);
-const TITLE: &str = "Rust 3D Renderer";
-
-const VELOCITY_CAP: f32 = 0.15;
+/// Top speed of the camera, in units per second
+const VELOCITY_CAP: f32 = 9.0;
+/// Acceleration of the camera while moving, in units per second squared
+const ACCELERATION: f32 = 64.0;
+/// Fraction of the velocity lost to friction every second, as a decay rate
+const FRICTION: f32 = 13.4;
+/// Top turning speed of the camera, in radians per second
+const LOOK_SPEED: f32 = 1.5;
 
 /// Application entry point
 pub fn main() {
-    // Create a new window
-    let (mut canvas, mut event_pump) = init_sdl();
-    let mut time_of_last_frame = SystemTime::now();
-
-    // Load the mesh
-    let model_mesh = Mesh::load_from_file("assets/teapot-trian.obj");
+    // Read the command line
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::usage());
+            return;
+        },
+        Err(error) => {
+            eprintln!("Error: {}\n\nFor more information, try '--help'.", error);
+            std::process::exit(2);
+        }
+    };
+
+    // Load the scene, or a scene with just the model
+    let loaded = if options.is_model() {
+        SceneFile::from_model(&options.path)
+    } else {
+        SceneFile::load(&options.path)
+    };
+    let SceneFile { mut scene, mut camera, frame_camera, background } = match loaded {
+        Ok(scene_file) => scene_file,
+        Err(error) => {
+            eprintln!("Error loading the scene: {}", error);
+            std::process::exit(1);
+        }
+    };
+    let bindings = match &options.bindings {
+        Some(path) => Bindings::load(path),
+        None => Ok(Bindings::default())
+    };
+    let bindings = match bindings {
+        Ok(bindings) => bindings,
+        Err(error) => {
+            eprintln!("Error loading the key bindings: {}", error);
+            std::process::exit(1);
+        }
+    };
+    if let Some(fov) = options.fov {
+        camera.fov = fov;
+    }
+    let mut settings = RenderSettings {
+        mode: options.render_mode,
+        shading: options.shading,
+        cull_mode: options.cull_mode,
+        front_face: options.front_face,
+        triangle_culling: true,
+        debug: options.debug_view,
+        show_normals: options.show_normals,
+        line_width: options.line_width,
+        antialiasing: options.antialiasing,
+        transparency: options.transparency,
+        texture_filter: options.texture_filter,
+        shadows: options.shadows,
+        normal_mapping: options.normal_maps,
+        threads: options.threads.unwrap_or_else(parallel::available_threads)
+    };
+
+    let (width, height) = (options.width, options.height);
+    let aspect_ratio = width as f32/height as f32; // Aspect ratio
+    place_camera(&mut camera, frame_camera, &scene, &options, aspect_ratio);
+
+    // Render to images without opening a window
+    if let Some(output) = &options.output {
+        if settings.mode == RenderMode::Gfx {
+            eprintln!("Error: the gfx render mode draws to the window, it can't render to images");
+            std::process::exit(2);
+        }
+        let offline = OfflineRender {
+            output: output.clone(),
+            width,
+            height,
+            frames: options.frames,
+            background,
+            depth: options.save_depth,
+            settings
+        };
+        match offline.run(&scene, &camera) {
+            Ok(paths) => println!("Saved {} image(s) to {}", paths.len(), paths.join(", ")),
+            Err(error) => {
+                eprintln!("Error rendering the scene: {}", error);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    // Matrices
-    let aspect_ratio = WIDTH as f32/HEIGHT as f32; // Aspect ratio
-    let proj_matrix = linear_transforms::projection_matrix(60.0, aspect_ratio, 0.1, 1000.0);
-    let translation_matrix = linear_transforms::translation_matrix(0.0, 0.0, -8.0);
+    // Create a new window
+    let (mut canvas, mut event_pump, mut controllers) = init_sdl(&options);
+    let texture_creator = canvas.texture_creator();
+    // The frame is rendered at a scale of the window and stretched to fill it
+    let (window_width, window_height) = canvas.output_size().unwrap();
+    let (render_width, render_height) = framebuffer::render_size(window_width, window_height, options.render_scale);
+    let mut screen = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, render_width as u32, render_height as u32)
+        .unwrap();
+    let mut framebuffer = Framebuffer::new(render_width, render_height);
+    let mut clock = Clock::new(options.fixed_step.map(|hz| Duration::from_secs_f64(1.0 / hz)));
+    let mut limiter = FrameLimiter::new(options.fps);
+
+    // On-screen overlay, the renderer still works without its font
+    let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string());
+    let mut hud = ttf_context.as_ref()
+        .map_err(|e| e.clone())
+        .and_then(|ttf_context| Hud::new(ttf_context, &options.font, options.hud));
+    if let Err(error) = &hud {
+        eprintln!("Warning: no overlay, {}", error);
+    }
+    let mut frame_times = FrameTimes::default();
+    let mut frame_start = Instant::now();
 
-    let mut camera = Vector3::new(0.0, 0.0, 0.0);
+    let mut frame_model = false;
+    let mut take_screenshot = false;
+    let mut toggle_recording = false;
+    let mut recorder: Option<Recorder> = None;
 
-    let mut theta:f32 = 0.0; // Rotations
+    let mut show_bindings = false;
+    let mut input = Input::new(bindings);
 
-    let mut moving: [bool; 6] = [false; 6]; // Up, Down, W, A, S, D
     let mut velocity = Vector3::new(0.0, 0.0, 0.0);
-
-    // Lighting
-    let mut light_dir = Vector3::new(-1.0, -1.0, 0.0);
-    light_dir.normalize_mut();
     'running: loop {
+        let mut resized = false;
         for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    break 'running
+            if let Event::Window { win_event: WindowEvent::SizeChanged(..), .. } = event {
+                resized = true;
+            }
+            if let Some(controllers) = &mut controllers {
+                controllers.handle_event(&event);
+            }
+            input.handle_event(&event);
+        }
+        for action in input.take_triggered() {
+            match action {
+                Action::Quit => break 'running,
+                Action::FrameScene => frame_model = true,
+                Action::ToggleTriangleCulling => settings.triangle_culling = !settings.triangle_culling,
+                Action::CycleRenderMode => settings.mode = settings.mode.next(),
+                Action::CycleDebugView => settings.debug = settings.debug.next(),
+                Action::ToggleNormals => settings.show_normals = !settings.show_normals,
+                Action::CycleAntialiasing => settings.antialiasing = settings.antialiasing.next(),
+                Action::CycleTransparency => settings.transparency = settings.transparency.next(),
+                Action::CycleTextureFilter => settings.texture_filter = settings.texture_filter.next(),
+                Action::ToggleShadows => settings.shadows = !settings.shadows,
+                Action::ToggleNormalMaps => settings.normal_mapping = !settings.normal_mapping,
+                Action::CycleCullMode => settings.cull_mode = settings.cull_mode.next(),
+                Action::FlipFrontFace => settings.front_face = settings.front_face.flip(),
+                Action::Screenshot => take_screenshot = true,
+                Action::ToggleRecording => toggle_recording = true,
+                Action::ToggleHud => {
+                    if let Ok(hud) = &mut hud {
+                        hud.visible = !hud.visible;
+                    }
+                },
+                Action::ShowBindings => show_bindings = !show_bindings,
+                Action::ToggleFullscreen => {
+                    let window = canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off
+                    };
+                    if let Err(error) = window.set_fullscreen(fullscreen) {
+                        eprintln!("Error toggling fullscreen: {}", error);
+                    }
                 },
-                // Up and down
-                Event::KeyDown { keycode: Some(Keycode::Space), ..} =>  {moving[0] = true;},
-                Event::KeyUp { keycode: Some(Keycode::Space), ..} =>    {moving[0] = false;},
-                Event::KeyDown { keycode: Some(Keycode::LShift), ..} => {moving[1] = true;},
-                Event::KeyUp { keycode: Some(Keycode::LShift), ..} =>   {moving[1] = false;},
-                // Forward and backward
-                Event::KeyDown { keycode: Some(Keycode::W), ..} =>      {moving[2] = true;},
-                Event::KeyUp { keycode: Some(Keycode::W), ..} =>        {moving[2] = false;},
-                Event::KeyDown { keycode: Some(Keycode::S), ..} =>      {moving[3] = true;},
-                Event::KeyUp { keycode: Some(Keycode::S), ..} =>        {moving[3] = false;},
-                // Left and right
-                Event::KeyDown { keycode: Some(Keycode::A), ..} =>      {moving[4] = true;},
-                Event::KeyUp { keycode: Some(Keycode::A), ..} =>        {moving[4] = false;},
-                Event::KeyDown { keycode: Some(Keycode::D), ..} =>      {moving[5] = true;},
-                Event::KeyUp { keycode: Some(Keycode::D), ..} =>        {moving[5] = false;},
+                // Movement is read from the held keys
                 _ => {}
             }
         }
-        // Movement
-        add_movement(&moving, &mut velocity);
-        camera += velocity;
-
-        // Clear the screen
-        canvas.set_draw_color(BG_COLOR);
-        canvas.clear();
-
-        // Update Matrices
-        theta += 0.015;
-        let rot_matrix_z = linear_transforms::rotation_matrix_z(theta * 0.7);
-        let rot_matrix_x = linear_transforms::rotation_matrix_x(theta);
-
-        // Calculate world matrix
-        let mut world_matrix = rot_matrix_z * rot_matrix_x;
-        world_matrix = translation_matrix * world_matrix;
-
-        // // View matrix
-        let looking_at = Vector3::new(0.0, 0.0, -1.0);
-        let up = Vector3::new(0.0, 1.0, 0.0);
-        // // println!("Camera: {:?}", camera);
-        // // let target = camera + looking_at;
-        let view_matrix = linear_transforms::view_matrix(camera, looking_at, up);
-        // println!(
-        //     "View matrix: x: {:?}, y: {:?}, z: {:?}",
-        //     view_matrix.m41,
-        //     view_matrix.m42,
-        //     view_matrix.m43
-        // );
-
-        // Draw Triangles
-        let mut triangles_to_raster = Mesh::new(Vec::new());
-        for triangle in model_mesh.tris.iter() {
-            // transform triangle
-            let mut transformed = triangle.clone();
-            transformed *= world_matrix;
-
-            // Move triangle to camera
-            let mut viewed = view_matrix * transformed;
-
-            // Calculate normal
-            let normal = viewed.normal();
-            // Calculate vector from camera to triangle
-            let mut vec_to_camera = viewed.p[0].xyz() - camera;
-            vec_to_camera.normalize_mut();
-            if normal.dot(&vec_to_camera) < 0.0 {
-                // Light and Color
-                let light_intensity = normal.dot(&light_dir) + 1.0;
-                viewed.c = Option::Some(Color::RGB(
-                    (light_intensity * 127.0) as u8,
-                    (light_intensity * 127.0) as u8,
-                    (light_intensity * 127.0) as u8
-                ));
-
-                // Project triangles from 3D to 2D
-                let mut projected = proj_matrix * viewed;
-                // Normalize the projected triangle
-                projected.p[0] /= projected.p[0].w;
-                projected.p[1] /= projected.p[1].w;
-                projected.p[2] /= projected.p[2].w;
-
-                // Scale into view
-                for i in 0..3 {
-                    projected.p[i].x += 1.0;
-                    projected.p[i].y += 1.0;
-                    projected.p[i].x *= 0.5 * WIDTH as f32;
-                    projected.p[i].y *= 0.5 * HEIGHT as f32;
+        // Render at the new size of the window
+        if resized {
+            let (window_width, window_height) = canvas.output_size().unwrap();
+            let (render_width, render_height) = framebuffer::render_size(window_width, window_height, options.render_scale);
+            if (render_width, render_height) != (framebuffer.width, framebuffer.height) {
+                framebuffer = Framebuffer::new(render_width, render_height);
+                screen = texture_creator
+                    .create_texture_streaming(PixelFormatEnum::RGB24, render_width as u32, render_height as u32)
+                    .unwrap();
+                // Recordings keep the size they started with
+                if let Some(recorder) = recorder.take() {
+                    println!("The window was resized, stopping the recording");
+                    stop_recording(recorder);
                 }
-
-                // Store triangle for rastering later
-                triangles_to_raster.tris.push(projected);
             }
         }
 
-        // Draw triangles to screen
-        triangles_to_raster.sort();
-        draw_mesh(&triangles_to_raster, &mut canvas);
-        triangles_to_raster.tris.clear();
+        // Advance the simulation by the time that passed. While recording
+        // a frame of video is always a frame of the simulation, so recordings
+        // play back at the frame rate no matter how long frames take to draw
+        let elapsed = clock.tick();
+        let steps = if recorder.is_some() {
+            vec![1.0 / options.fps as f32]
+        } else {
+            clock.steps(elapsed)
+        };
+        for dt in steps {
+            // Movement
+            add_movement(input.movement(), &mut velocity, dt);
+            camera.move_local(velocity * dt);
+            let look = input.look() * LOOK_SPEED * dt;
+            camera.rotate(look.x, look.y);
+
+            // Animate the scene
+            scene.update(dt);
+        }
+
+        // Place the camera so the whole scene is in view
+        if frame_model {
+            camera.frame(&scene.bounding_sphere(), framebuffer.width as f32 / framebuffer.height as f32);
+            velocity = Vector3::new(0.0, 0.0, 0.0);
+            frame_model = false;
+        }
+
+        // Draw the scene
+        framebuffer.clear(background);
+        // The gfx mode draws the triangles on the canvas later, over the background
+        let (gfx_tris, mut stats) = if settings.mode == RenderMode::Gfx {
+            render::project_scene(&scene, &camera, &settings, framebuffer.width, framebuffer.height)
+        } else {
+            (Projected::default(), render::render_scene(&scene, &camera, &settings, &mut framebuffer))
+        };
+        let present_start = Instant::now();
+        screen.update(None, &framebuffer.pixels, framebuffer.width * 3).unwrap();
+        canvas.copy(&screen, None, None).unwrap();
+        if settings.mode == RenderMode::Gfx {
+            let gfx_start = Instant::now();
+            let (window_width, window_height) = canvas.output_size().unwrap();
+            let scale = (
+                window_width as f32 / framebuffer.width as f32,
+                window_height as f32 / framebuffer.height as f32
+            );
+            draw_gfx(&mut canvas, gfx_tris, scale);
+            // Count it as rasterization, not presenting
+            stats.raster_time = gfx_start.elapsed();
+        }
+
+        // Save what is on screen, which the gfx mode drew on the canvas
+        // instead of the framebuffer, without a depth buffer
+        let save_depth = options.save_depth && settings.mode != RenderMode::Gfx;
+        if settings.mode == RenderMode::Gfx && (take_screenshot || recorder.is_some() || toggle_recording) {
+            if let Err(error) = read_canvas(&canvas, &mut framebuffer) {
+                eprintln!("Error reading the window: {}", error);
+            }
+        }
+        if take_screenshot {
+            match screenshot::save(&framebuffer, &camera, &options.screenshot_dir, save_depth) {
+                Ok(paths) => println!("Saved screenshot to {}", paths.join(", ")),
+                Err(error) => eprintln!("Error saving the screenshot: {}", error)
+            }
+            take_screenshot = false;
+        }
+        if toggle_recording {
+            recorder = match recorder.take() {
+                Some(recorder) => {
+                    stop_recording(recorder);
+                    None
+                },
+                None => match Recorder::start(
+                    &options.record_dir, options.record_format, framebuffer.width, framebuffer.height, options.fps
+                ) {
+                    Ok(recorder) => {
+                        println!("Recording to {}", recorder.path);
+                        Some(recorder)
+                    },
+                    Err(error) => {
+                        eprintln!("Error starting the recording: {}", error);
+                        None
+                    }
+                }
+            };
+            toggle_recording = false;
+        }
+        if let Some(recording) = &mut recorder {
+            if let Err(error) = recording.add_frame(&framebuffer) {
+                eprintln!("Error recording the frame: {}", error);
+                recorder = None;
+            }
+        }
 
         // Draw a Rect in the position of the camera
+        let (window_width, window_height) = canvas.output_size().unwrap();
         canvas.set_draw_color(Color::RGB(255, 0, 0));
         canvas.draw_rect(Rect::new(
-            ((camera.x + 1.0) * 0.5 * WIDTH as f32) as i32,
-            ((camera.y + 1.0) * 0.5 * HEIGHT as f32) as i32,
+            ((camera.position.x + 1.0) * 0.5 * window_width as f32) as i32,
+            ((camera.position.y + 1.0) * 0.5 * window_height as f32) as i32,
             4, 4
         )).unwrap();
 
+        // Draw the overlay, or the key bindings in its place
+        if let Ok(hud) = &hud {
+            let lines = if show_bindings {
+                input.bindings.describe()
+            } else if hud.visible {
+                hud::lines(&frame_times, &stats, &camera, &settings)
+            } else {
+                Vec::new()
+            };
+            if let Err(error) = hud.draw(&mut canvas, &texture_creator, &lines) {
+                eprintln!("Error drawing the overlay: {}", error);
+            }
+        }
+
         // Update the screen
+        let recording_status = recorder.as_ref()
+            .map(|recorder| format!(" - recording, {} frames", recorder.frames))
+            .unwrap_or_default();
+        canvas.window_mut().set_title(&format!(
+            "{} - {}/{} objects culled, {}/{} triangles culled{}",
+            options.title,
+            stats.objects_culled, stats.objects,
+            stats.triangles_culled, stats.triangles,
+            recording_status
+        )).unwrap();
         canvas.present();
+        let mut present_time = present_start.elapsed();
+        if settings.mode == RenderMode::Gfx {
+            present_time = present_time.saturating_sub(stats.raster_time);
+        }
 
         // Cap FPS
-        limit_fps(&mut time_of_last_frame, FPS_CAP);
+        limiter.wait();
+
+        let now = Instant::now();
+        frame_times.add(now - frame_start, &stats, present_time);
+        frame_start = now;
+    }
+
+    // Don't leave a recording unfinished
+    if let Some(recorder) = recorder {
+        stop_recording(recorder);
     }
 }
 
-/// Add movement to velocity
-fn add_movement(moving: &[bool; 6], velocity: &mut Vector3<f32>) {
-    if velocity.magnitude() < VELOCITY_CAP {
-        let mut acceleration = Vector3::new(0.0, 0.0, 0.0);
-        if moving[0] {acceleration += Vector3::new(0.0, -1.0, 0.0);}
-        if moving[1] {acceleration += Vector3::new(0.0, 1.0, 0.0);}
-        if moving[2] {acceleration += Vector3::new(0.0, 0.0, 1.0);}
-        if moving[3] {acceleration += Vector3::new(0.0, 0.0, -1.0);}
-        if moving[4] {acceleration += Vector3::new(-1.0, 0.0, 0.0);}
-        if moving[5] {acceleration += Vector3::new(1.0, 0.0, 0.0);}
+/// Ends the recording and tells where it went
+fn stop_recording(recorder: Recorder) {
+    let (path, frames) = (recorder.path.clone(), recorder.frames);
+    match recorder.finish() {
+        Ok(()) => println!("Saved {} recorded frames to {}", frames, path),
+        Err(error) => eprintln!("Error finishing the recording: {}", error)
+    }
+}
 
+/// Add movement in the `direction` to velocity, over `dt` seconds
+///
+/// The direction and velocity are relative to the camera:
+/// x is right, y is up and z is forward.
+fn add_movement(direction: Vector3<f32>, velocity: &mut Vector3<f32>, dt: f32) {
+    if velocity.magnitude() < VELOCITY_CAP {
+        let mut acceleration = direction;
         if acceleration.magnitude() > 0.0 {
-            acceleration *= 0.02;
+            acceleration *= ACCELERATION * dt;
             *velocity += acceleration;
         }
     }
-    // Some friction
-    *velocity *= 0.8;
+    // Some friction, decaying the same amount per second at any frame rate
+    *velocity *= (-FRICTION * dt).exp();
+}
+
+/// Draws the triangles with SDL_gfx on the canvas, scaled from the
+/// framebuffer to the window
+///
+/// There is no depth buffer, so they are drawn from the furthest to the
+/// closest, the transparent ones after the opaque ones and alpha blended
+/// whatever their blend mode.
+fn draw_gfx(canvas: &mut Canvas<Window>, projected: Projected, scale: (f32, f32)) {
+    let mut tris = projected.opaque;
+    tris.sort_by(|a, b| b.midpoint().z.total_cmp(&a.midpoint().z));
+    canvas.set_scale(scale.0, scale.1).unwrap();
+    for tri in tris.iter().chain(projected.transparent.iter().map(|(tri, _)| tri)) {
+        tri.draw_gfx(canvas);
+    }
+    canvas.set_scale(1.0, 1.0).unwrap();
 }
 
-/// Draw a mesh to the canvas given
-fn draw_mesh(mesh: &Mesh, canvas: &mut Canvas<Window>) {
-    for triangle in mesh.tris.iter() {
-        // Draw half the screen with draw, the other half with draw_gfx
-        // if triangle.midpoint().x > WIDTH as f32/1.7 {
-        //     triangle.draw_outline(canvas);
-        // } else {
-        triangle.draw(canvas);
-        // }
+/// Reads back what is drawn on the canvas into the framebuffer, scaled
+/// to its size
+fn read_canvas(canvas: &Canvas<Window>, framebuffer: &mut Framebuffer) -> Result<(), String> {
+    let (window_width, window_height) = canvas.output_size()?;
+    let (window_width, window_height) = (window_width as usize, window_height as usize);
+    let pixels = canvas.read_pixels(None, PixelFormatEnum::RGB24)?;
+    for y in 0..framebuffer.height {
+        for x in 0..framebuffer.width {
+            let (wx, wy) = (x * window_width / framebuffer.width, y * window_height / framebuffer.height);
+            let (i, j) = ((y * framebuffer.width + x) * 3, (wy * window_width + wx) * 3);
+            framebuffer.pixels[i..i + 3].copy_from_slice(&pixels[j..j + 3]);
+        }
     }
+    Ok(())
 }
 
-/// Limit the FPS to the given cap
-fn limit_fps(prev_time: &mut SystemTime, fps_cap: u64) {
-    let time_difference = SystemTime::now().duration_since(*prev_time).unwrap();
-    if time_difference < Duration::from_millis(1000 / fps_cap) {
-        std::thread::sleep(Duration::from_millis(1000 / fps_cap) - time_difference);
+/// Places the camera as given in the command line, or to frame the scene
+fn place_camera(camera: &mut Camera, frame_camera: bool, scene: &Scene, options: &Options, aspect_ratio: f32) {
+    if let Some(position) = options.camera_position {
+        camera.position = position;
+    }
+    if let Some(target) = options.camera_target {
+        if target != camera.position {
+            camera.direction = (target - camera.position).normalize();
+        }
+    }
+    if frame_camera && options.camera_position.is_none() {
+        camera.frame(&scene.bounding_sphere(), aspect_ratio);
     }
-    *prev_time = SystemTime::now();
 }
 
 /// Initialize SDL
-fn init_sdl() -> (Canvas<Window>, EventPump) {
+///
+/// Game controllers are optional, the viewer works without them.
+fn init_sdl(options: &Options) -> (Canvas<Window>, EventPump, Option<Controllers>) {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
-    let window = video_subsystem
-        .window(TITLE, WIDTH as u32, HEIGHT as u32)
-        .position_centered()
-        .build()
-        .unwrap();
+    let mut window = video_subsystem.window(&options.title, options.width as u32, options.height as u32);
+    window.position_centered().resizable();
+    if options.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().unwrap();
 
     let canvas = window.into_canvas().build().unwrap();
     let event_pump = sdl_context.event_pump().unwrap();
+    let controllers = match sdl_context.game_controller() {
+        Ok(subsystem) => Some(Controllers::new(subsystem)),
+        Err(error) => {
+            eprintln!("Warning: no game controllers, {}", error);
+            None
+        }
+    };
 
-    (canvas, event_pump)
+    (canvas, event_pump, controllers)
 }
@@ -0,0 +1,61 @@
+/*!
+# material.rs
+
+Surface properties of the objects in the scene.
+*/
+
+use sdl2::pixels::Color;
+
+/// How the color of a surface is combined with what is behind it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Mixed with what is behind by the opacity, opaque at full opacity
+    Alpha,
+    /// Added to what is behind, brightening it, like light or fire
+    Additive,
+    /// Multiplied with what is behind, darkening it, like tinted glass
+    Multiply
+}
+
+/// How the surface of an object looks
+#[derive(Clone, Debug)]
+pub struct Material {
+    #[allow(dead_code)]
+    pub name: String,
+    /// Base color of the surface, before lighting
+    pub color: Color,
+    /// How much of what is behind the surface it hides, from 0 to 1
+    pub opacity: f32,
+    pub blend: BlendMode,
+    /// Index of the texture in the scene multiplying the color, if any
+    pub texture: Option<usize>,
+    /// Index of the texture in the scene bending the normals of the
+    /// surface, in the space of its tangents, if any
+    pub normal_map: Option<usize>
+}
+
+impl Material {
+    /// Creates an opaque material with the given color
+    pub fn new(name: &str, color: Color) -> Self {
+        Self {
+            name: name.to_string(),
+            color,
+            opacity: 1.0,
+            blend: BlendMode::Alpha,
+            texture: None,
+            normal_map: None
+        }
+    }
+
+    /// Whether what is behind the surface shows through it, so it has to
+    /// be drawn after the opaque surfaces
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0 || self.blend != BlendMode::Alpha
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::new("default", Color::WHITE)
+    }
+}
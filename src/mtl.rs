@@ -0,0 +1,139 @@
+/*!
+# mtl.rs
+
+Material libraries of .obj files.
+
+A .mtl file declares materials with `newmtl`, followed by their
+properties. Only the ones this renderer can show are read:
+
+- `Kd` the diffuse color, from 0 to 1
+- `d` the opacity, or `Tr` its opposite
+- `map_Kd` the image multiplying the color
+- `map_Bump`, `bump` or `norm` the tangent space normal map
+
+Paths of images come after any options and can have spaces, they are
+relative to the library.
+*/
+
+use std::fs;
+
+use sdl2::pixels::Color;
+
+/// A material of a library, with the paths of its images
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub color: Color,
+    pub opacity: f32,
+    pub texture: Option<String>,
+    pub normal_map: Option<String>
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            color: Color::WHITE,
+            opacity: 1.0,
+            texture: None,
+            normal_map: None
+        }
+    }
+}
+
+/// Reads the materials of the library at the given path
+pub fn load(path: &str) -> Result<Vec<MtlMaterial>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(parse(&contents))
+}
+
+/// Reads the materials of the contents of a library, skipping what it
+/// doesn't understand
+pub fn parse(contents: &str) -> Vec<MtlMaterial> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    for line in contents.lines() {
+        let elements: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, values) = match elements.split_first() {
+            Some((keyword, values)) => (*keyword, values),
+            None => continue
+        };
+        if keyword == "newmtl" {
+            materials.push(MtlMaterial::new(&values.join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => continue
+        };
+        let numbers: Vec<f32> = values.iter().filter_map(|value| value.parse().ok()).collect();
+        let path = image_path(values);
+        match (keyword, numbers.as_slice()) {
+            ("Kd", [r, g, b, ..]) => {
+                let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+                material.color = Color::RGB(channel(*r), channel(*g), channel(*b));
+            },
+            ("d", [d, ..]) => material.opacity = d.clamp(0.0, 1.0),
+            ("Tr", [tr, ..]) => material.opacity = 1.0 - tr.clamp(0.0, 1.0),
+            ("map_Kd", _) => material.texture = path,
+            ("map_Bump" | "map_bump" | "bump" | "norm", _) => material.normal_map = path,
+            _ => ()
+        }
+    }
+    materials
+}
+
+/// Path of the image of a map statement, after its options
+fn image_path(values: &[&str]) -> Option<String> {
+    let mut rest = values;
+    while let Some((option, arguments)) = rest.split_first().filter(|(value, _)| value.starts_with('-')) {
+        // Most options take one argument, the offset, scale and turbulence
+        // take up to 3 numbers
+        let (required, optional) = match *option {
+            "-o" | "-s" | "-t" => (1, 2),
+            "-mm" => (2, 0),
+            _ => (1, 0)
+        };
+        let arguments = arguments.get(required..).unwrap_or_default();
+        let numbers = arguments.iter().take(optional).take_while(|value| value.parse::<f32>().is_ok()).count();
+        rest = &arguments[numbers..];
+    }
+    Some(rest.join(" ")).filter(|path| !path.is_empty())
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let materials = parse("
+            # Exported
+            newmtl brick wall
+            Kd 0.8 0.4 0.0
+            map_Kd textures/brick.png
+            map_Kd -o 0.5 0.5 -clamp on textures/brick wall.png
+            map_Bump -bm 0.5 textures/brick_normal.png
+
+            newmtl glass
+            Ns 96.0
+            d 0.25
+            norm glass_n.png
+        ");
+        assert_eq!(materials, vec![
+            MtlMaterial {
+                name: "brick wall".to_string(),
+                color: Color::RGB(204, 102, 0),
+                opacity: 1.0,
+                texture: Some("textures/brick wall.png".to_string()),
+                normal_map: Some("textures/brick_normal.png".to_string())
+            },
+            MtlMaterial {
+                opacity: 0.25,
+                normal_map: Some("glass_n.png".to_string()),
+                ..MtlMaterial::new("glass")
+            }
+        ]);
+    }
+}
@@ -0,0 +1,88 @@
+/*!
+# offline.rs
+
+Headless rendering to image files, without opening a window.
+
+Renders a single frame, or a turntable of frames with the camera
+orbiting around the scene, and saves them as PNG or PPM images.
+*/
+
+use na::{Rotation3, Vector3};
+
+use crate::camera::Camera;
+use crate::framebuffer::{self, Framebuffer};
+use crate::render::{self, RenderSettings};
+use crate::scene::Scene;
+use sdl2::pixels::Color;
+
+/// What to render and where to save it
+pub struct OfflineRender {
+    /// Path of the image, frames of a turntable get their number appended
+    pub output: String,
+    pub width: usize,
+    pub height: usize,
+    /// Number of frames in a full turn of the camera, 1 for a still image
+    pub frames: usize,
+    pub background: Color,
+    /// Also save the depth buffer of each frame as a grayscale image
+    pub depth: bool,
+    pub settings: RenderSettings
+}
+
+impl OfflineRender {
+    /// Renders the frames and saves them, returning the paths written
+    pub fn run(&self, scene: &Scene, camera: &Camera) -> Result<Vec<String>, String> {
+        let mut framebuffer = Framebuffer::new(self.width, self.height);
+        let center = scene.bounding_sphere().center;
+        let mut written = Vec::new();
+
+        for frame in 0..self.frames {
+            // Orbit the camera around the vertical axis through the center of the scene
+            let angle = frame as f32 / self.frames as f32 * std::f32::consts::TAU;
+            let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), angle);
+            let mut orbiting = *camera;
+            orbiting.position = center + rotation * (camera.position - center);
+            orbiting.direction = rotation * camera.direction;
+
+            framebuffer.clear(self.background);
+            render::render_scene(scene, &orbiting, &self.settings, &mut framebuffer);
+
+            let path = frame_path(&self.output, frame, self.frames);
+            framebuffer.save(&path)?;
+            let depth_path = framebuffer::with_suffix(&path, "_depth");
+            written.push(path);
+            if self.depth {
+                framebuffer.save_depth(&depth_path, orbiting.near, orbiting.far)?;
+                written.push(depth_path);
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// Path of a frame, with its zero padded number before the extension
+/// when there is more than one
+///
+/// `frame_path("out.png", 3, 24)` is `out_03.png`.
+pub fn frame_path(output: &str, frame: usize, frames: usize) -> String {
+    if frames <= 1 {
+        return output.to_string();
+    }
+    let digits = (frames - 1).to_string().len();
+    framebuffer::with_suffix(output, &format!("_{:0digits$}", frame, digits = digits))
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_path() {
+        assert_eq!(frame_path("out.png", 0, 1), "out.png");
+        assert_eq!(frame_path("out.png", 3, 24), "out_03.png");
+        assert_eq!(frame_path("renders/out.ppm", 7, 8), "renders/out_7.ppm");
+        assert_eq!(frame_path("out", 12, 100), "out_12");
+    }
+}
@@ -0,0 +1,64 @@
+/*!
+# parallel.rs
+
+Splits work over many independent items across threads, for the parts
+of the pipeline that do the same thing to every triangle or tile.
+
+The results always come back in the order of the items, so the frame
+is the same however many threads rendered it.
+*/
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Number of threads the machine can run at once
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// Calls `f` on every item with up to `threads` threads, returning the
+/// results in the order of the items
+///
+/// Each thread takes the next item when it is done with one, so a few
+/// slow items don't keep the other threads waiting.
+pub fn map<T: Sync, R: Send, F: Fn(&T) -> R + Sync>(items: &[T], threads: usize, f: F) -> Vec<R> {
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(items.len()))
+            .map(|_| scope.spawn(|| {
+                let mut done = Vec::new();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= items.len() {
+                        break done;
+                    }
+                    done.push((i, f(&items[i])));
+                }
+            }))
+            .collect();
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("a render thread panicked"))
+            .collect()
+    });
+    results.sort_unstable_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let items: Vec<u64> = (0..1000).collect();
+        let squares = map(&items, 4, |&i| i * i);
+        assert_eq!(squares, items.iter().map(|&i| i * i).collect::<Vec<_>>());
+        assert_eq!(map(&items[..0], 4, |&i| i), Vec::<u64>::new());
+    }
+}
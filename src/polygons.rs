@@ -7,9 +7,13 @@ A library of utilities for the renderer.
 Implementations for the `Tri` and `Mesh` structs, such as
 `Mesh::load_from_file()` and `Tri::draw()`.
 */
-use na::{Vector3, Vector4, Matrix4, Matrix, U4, ArrayStorage};
+use na::{Vector2, Vector3, Vector4, Matrix4, Matrix, U4, ArrayStorage};
 
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::raster;
 
+
+use std::collections::HashMap;
 use std::ops::{AddAssign, Mul, MulAssign, DivAssign};
 use std::fs;
 use std::io::Read;
@@ -18,23 +22,48 @@ use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::rect::Point;
 
 
-use sdl2::render::Canvas;
+use sdl2::render::{BlendMode, Canvas};
 use sdl2::pixels::Color;
 use sdl2::video::Window;
 // use sdl2::rect::Point;
 
 
+/// Position, normal, texture coordinates, tangent and world position of
+/// a vertex of a triangle
+type VertexAttributes = (Vector4<f32>, Vector3<f32>, Vector2<f32>, Vector4<f32>, Vector3<f32>);
+
 /// A 3D triangle, with 3 vertices
 /// and an optional color
 #[derive(Clone, Copy, Debug)]
 pub struct Tri {
     pub p: [Vector4<f32>; 3],
-    pub c: Option<Color>
+    pub c: Option<Color>,
+    /// Normal of each vertex, zero when the model has none
+    pub n: [Vector3<f32>; 3],
+    /// Texture coordinates of each vertex
+    pub uv: [Vector2<f32>; 3],
+    /// Tangent of each vertex, where u grows along the surface, with in w
+    /// the sign of the bitangent, where v grows, relative to the cross
+    /// product of the normal and the tangent; zero when it has none
+    pub tangent: [Vector4<f32>; 3],
+    /// Position of each vertex in world space, kept through the
+    /// projection to light the triangle per pixel
+    pub world: [Vector3<f32>; 3],
+    /// Index of the texture the triangle is colored with, in the
+    /// `Projected` triangles it is in
+    pub texture: Option<usize>,
+    /// Index of the tangent space normal map of the triangle, like `texture`
+    pub normal_map: Option<usize>
 }
 
 /// A Vector of 3D triangles
 pub struct Mesh {
-    pub tris: Vec<Tri>
+    pub tris: Vec<Tri>,
+    /// Material library the .obj file refers to, relative to it
+    pub library: Option<String>,
+    /// Materials of the library the triangles use, with the index of the
+    /// first triangle using each, in order
+    pub material_groups: Vec<(String, usize)>
 }
 
 impl Tri {
@@ -43,7 +72,8 @@ impl Tri {
     pub fn new(p1: Vector4<f32>, p2: Vector4<f32> , p3: Vector4<f32>, color: Color) -> Self {
         Self {
             p: [p1, p2, p3],
-            c: Some(color)
+            c: Some(color),
+            ..Self::from([p1, p2, p3])
         }
     }
 
@@ -58,6 +88,16 @@ impl Tri {
         line1.cross(&line2).normalize()
     }
 
+    /// Signed area of the triangle projected to the xy plane
+    ///
+    /// Positive when the vertices appear clockwise on a screen
+    /// with y pointing down, negative when counter-clockwise.
+    pub fn signed_area(&self) -> f32 {
+        let line1 = self.p[1] - self.p[0];
+        let line2 = self.p[2] - self.p[0];
+        (line1.x * line2.y - line2.x * line1.y) * 0.5
+    }
+
     /// Calculates the center of the triangle
     pub fn midpoint(&self) -> Vector3<f32> {
         let mut midpoint = Vector3::new(0.0, 0.0, 0.0);
@@ -70,6 +110,67 @@ impl Tri {
         midpoint
     }
 
+    /// Clips the triangle against a plane, keeping the part on the
+    /// side the normal points to
+    ///
+    /// Outputs 0, 1 or 2 triangles, depending on how many vertices
+    /// are inside. The new vertices keep the winding of the triangle.
+    pub fn clip_against_plane(&self, plane_point: Vector3<f32>, plane_normal: Vector3<f32>) -> Vec<Tri> {
+        let plane_normal = plane_normal.normalize();
+        let distance = |p: &Vector4<f32>| plane_normal.dot(&(p.xyz() - plane_point));
+        // Vertex where the edge from vertex `a` to vertex `b` crosses the plane,
+        // with its attributes interpolated along the edge
+        let intersect = |a: usize, b: usize| {
+            let t = distance(&self.p[a]) / (distance(&self.p[a]) - distance(&self.p[b]));
+            (
+                self.p[a] + (self.p[b] - self.p[a]) * t,
+                self.n[a] + (self.n[b] - self.n[a]) * t,
+                self.uv[a] + (self.uv[b] - self.uv[a]) * t,
+                self.tangent[a] + (self.tangent[b] - self.tangent[a]) * t,
+                self.world[a] + (self.world[b] - self.world[a]) * t
+            )
+        };
+
+        let inside: Vec<usize> = (0..3).filter(|&i| distance(&self.p[i]) >= 0.0).collect();
+        match inside.len() {
+            0 => Vec::new(),
+            3 => vec![*self],
+            1 => {
+                // Keep the inside vertex, and move the other two onto the plane
+                let i = inside[0];
+                let (a, b) = ((i + 1) % 3, (i + 2) % 3);
+                let mut tri = *self;
+                tri.set_vertex(a, intersect(i, a));
+                tri.set_vertex(b, intersect(i, b));
+                vec![tri]
+            },
+            _ => {
+                // The inside forms a quad, split it in two triangles
+                let o = (0..3).find(|i| !inside.contains(i)).unwrap();
+                let (a, b) = ((o + 1) % 3, (o + 2) % 3);
+                let a_cut = intersect(a, o);
+                let b_cut = intersect(b, o);
+
+                let mut first = *self;
+                first.set_vertex(o, b_cut);
+                let mut second = *self;
+                second.set_vertex(o, a_cut);
+                second.set_vertex(b, b_cut);
+                vec![first, second]
+            }
+        }
+    }
+
+    /// Replaces the position, normal, texture coordinates, tangent and
+    /// world position of a vertex
+    fn set_vertex(&mut self, i: usize, (p, n, uv, tangent, world): VertexAttributes) {
+        self.p[i] = p;
+        self.n[i] = n;
+        self.uv[i] = uv;
+        self.tangent[i] = tangent;
+        self.world[i] = world;
+    }
+
     /// Draws the filled triangle to the given canvas with a local
     /// implementation of the bresenham line algorithm
     #[allow(dead_code)]
@@ -91,22 +192,22 @@ impl Tri {
         Self::scanline_fill(outline, canvas);
     }
 
-    /// Draws only the outline of the triangle to the given canvas
-    pub fn draw_outline(&self, canvas: &mut Canvas<Window>) {
-        canvas.set_draw_color(self.c.unwrap_or(Color::GREEN));
+    /// Draws only the outline of the triangle to the given canvas,
+    /// with anti-aliased lines `width` pixels wide
+    pub fn draw_outline(&self, canvas: &mut Canvas<Window>, width: f32) {
+        let color = self.c.unwrap_or(Color::GREEN);
+        let (w, h) = canvas.output_size().unwrap();
+        canvas.set_blend_mode(BlendMode::Blend);
 
-        // Use the Bresenham algorithm to get the outline of the triangle
-        let mut outline = Vec::new();
+        // Each pixel is blended with the line by how much of it is covered
         for i in 0..3 {
-            let p1 = self.p[i];
-            let p2 = self.p[(i + 1) % 3];
-            let mut line = Self::bresenham_line(p1.x as i16, p1.y as i16, p2.x as i16, p2.y as i16);
-            outline.append(&mut line);
-        }
-
-        for point in outline.iter() {
-            canvas.draw_point(Point::new(point[0] as i32, point[1] as i32)).unwrap();
+            let size = (w as usize, h as usize);
+            raster::line_coverage(size, &self.p[i], &self.p[(i + 1) % 3], width, |x, y, _, coverage| {
+                canvas.set_draw_color(Color::RGBA(color.r, color.g, color.b, (coverage * 255.0).round() as u8));
+                canvas.draw_point(Point::new(x as i32, y as i32)).unwrap();
+            });
         }
+        canvas.set_blend_mode(BlendMode::None);
     }
 
     /// Uses the sdl2::gfx::primitives::filled_trigon function to draw the triangle
@@ -190,14 +291,11 @@ impl Tri {
 
 impl From<[f32; 9]> for Tri {
     fn from(p: [f32; 9]) -> Self {
-        Self {
-            p: [
-                Vector4::new(p[0], p[1], p[2], 1.0),
-                Vector4::new(p[3], p[4], p[5], 1.0),
-                Vector4::new(p[6], p[7], p[8], 1.0)
-            ],
-            c: None
-        }
+        Self::from([
+            Vector4::new(p[0], p[1], p[2], 1.0),
+            Vector4::new(p[3], p[4], p[5], 1.0),
+            Vector4::new(p[6], p[7], p[8], 1.0)
+        ])
     }
 }
 
@@ -205,7 +303,13 @@ impl From<[Vector4<f32>; 3]> for Tri {
     fn from(p: [Vector4<f32>; 3]) -> Self {
         Self {
             p: [p[0], p[1], p[2]],
-            c: None
+            c: None,
+            n: [Vector3::zeros(); 3],
+            uv: [Vector2::zeros(); 3],
+            tangent: [Vector4::zeros(); 3],
+            world: [Vector3::zeros(); 3],
+            texture: None,
+            normal_map: None
         }
     }
 }
@@ -228,7 +332,7 @@ impl Mul<Matrix<f32, U4, U4, ArrayStorage<f32, 4, 4>>> for Tri {
                 other * self.p[1],
                 other * self.p[2]
             ],
-            c: self.c
+            ..self
         }
     }
 }
@@ -244,7 +348,7 @@ impl Mul<Tri> for Matrix4<f32> {
                 self * other.p[1],
                 self * other.p[2]
             ],
-            c: other.c
+            ..other
         }
     }
 }
@@ -267,58 +371,170 @@ impl DivAssign<f32> for Tri {
 }
 
 
+/// Position in a list of `len` elements of an index of a face of a .obj
+/// file, counting from 1, or back from the end of the list when negative
+fn obj_index(value: &str, len: usize, kind: &str) -> Result<usize, String> {
+    let index: i64 = value.parse().map_err(|_| format!("invalid {} index \"{}\"", kind, value))?;
+    let position = if index < 0 { len as i64 + index } else { index - 1 };
+    if index == 0 || position < 0 || position >= len as i64 {
+        return Err(format!("no {} {}", kind, index));
+    }
+    Ok(position as usize)
+}
+
 impl Mesh {
     /// Creates a new mesh from a list of triangles
     pub fn new(tris: Vec<Tri>) -> Self {
-        Self { tris }
+        Self { tris, library: None, material_groups: Vec::new() }
     }
 
     /// Loads a mesh from a .obj file
-    pub fn load_from_file(filename: &str) -> Self {
+    pub fn load_from_file(filename: &str) -> Result<Self, String> {
+        let mut file = fs::File::open(filename).map_err(|e| e.to_string())?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).map_err(|e| e.to_string())?;
+
+        let mesh = Self::parse_obj(&contents)?;
+        println!("Loaded {} triangles", mesh.tris.len());
+        Ok(mesh)
+    }
+
+    /// Reads a mesh from the contents of a .obj file, failing on the
+    /// first line that can't be read
+    pub fn parse_obj(contents: &str) -> Result<Self, String> {
         let mut tris = Vec::new();
-        let mut tri: [Vector4<f32>; 3];
         let mut vertex: Vector4<f32>;
         let mut buffer: Vec<Vector4<f32>> = Vec::new();
-        let mut relations: [Vec<i32>;3];
+        let mut texcoords: Vec<Vector2<f32>> = Vec::new();
+        let mut normals: Vec<Vector3<f32>> = Vec::new();
         let mut line_elements: Vec<&str>;
 
-
-        let mut file = fs::File::open(filename).unwrap();
-        let mut contents = String::new();
-
-        file.read_to_string(&mut contents).unwrap();
-
+        let mut library = None;
+        let mut material_groups = Vec::new();
         let lines: Vec<&str> = contents.split("\n").collect();
-        for line in lines {
+        for (number, line) in lines.iter().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let float = |value: &str| value.parse::<f32>()
+                .map_err(|_| error(format!("invalid number \"{}\"", value)));
+            // Names of materials and libraries can have spaces
+            match line.trim().split_once(' ') {
+                Some(("mtllib", name)) => library = Some(name.trim().to_string()),
+                Some(("usemtl", name)) => material_groups.push((name.trim().to_string(), tris.len())),
+                _ => ()
+            }
             line_elements = line.split(" ").map(|s| s.trim()).collect();
             match line_elements[..] {
                 ["v", x, y, z, ..] => {
-                    // println!("Vertex: {}, {}, {}", x, y, z);
-                    vertex = Vector4::new(x.parse().unwrap(), y.parse().unwrap(), z.parse().unwrap(), 1.0);
+                    vertex = Vector4::new(float(x)?, float(y)?, float(z)?, 1.0);
                     buffer.push(vertex);
                 },
+                ["vt", u, v, ..] => {
+                    texcoords.push(Vector2::new(float(u)?, float(v)?));
+                },
+                ["vn", x, y, z, ..] => {
+                    normals.push(Vector3::new(float(x)?, float(y)?, float(z)?));
+                },
                 ["f", v1, v2, v3, ..] => {
-                    // A triangle
-                    relations = [
-                        v1.split("/").map(|s| s.parse().unwrap()).collect(),
-                        v2.split("/").map(|s| s.parse().unwrap()).collect(),
-                        v3.split("/").map(|s| s.parse().unwrap()).collect()
-                    ];
-                    tri = [
-                        buffer[relations[0][0] as usize - 1],
-                        buffer[relations[1][0] as usize - 1],
-                        buffer[relations[2][0] as usize - 1]
-                    ];
-                    tris.push(Tri::from(tri));
+                    // A triangle, its vertices are `v`, `v/vt`, `v//vn` or `v/vt/vn`
+                    let mut t = Tri::from([Vector4::zeros(); 3]);
+                    for (i, corner) in [v1, v2, v3].into_iter().enumerate() {
+                        let mut indices = corner.split('/');
+                        let mut index = |len: usize, kind: &str| match indices.next() {
+                            None | Some("") => Ok(None),
+                            Some(value) => obj_index(value, len, kind).map(Some).map_err(&error)
+                        };
+                        let v = index(buffer.len(), "vertex")?.ok_or_else(|| error("missing vertex".to_string()))?;
+                        t.p[i] = buffer[v];
+                        if let Some(vt) = index(texcoords.len(), "texture coordinate")? {
+                            t.uv[i] = texcoords[vt];
+                        }
+                        if let Some(vn) = index(normals.len(), "normal")? {
+                            t.n[i] = normals[vn].normalize();
+                        }
+                    }
+                    tris.push(t);
                 },
                 _ => ()
             }
         }
-        println!("Loaded {} triangles", tris.len());
-        Self::new(tris)
+        let mut mesh = Self { tris, library, material_groups };
+        mesh.compute_tangents();
+        Ok(mesh)
+    }
+
+    /// Computes the tangent of every vertex from the texture coordinates
+    ///
+    /// The tangent of each triangle points where u grows on its surface,
+    /// and its bitangent where v grows. Vertices at the same position,
+    /// with the same normal and texture coordinates, add up the tangents
+    /// of their triangles, made perpendicular to their normal.
+    pub fn compute_tangents(&mut self) {
+        let key = |tri: &Tri, i: usize| {
+            let (p, n, uv) = (tri.p[i], tri.n[i], tri.uv[i]);
+            [p.x, p.y, p.z, n.x, n.y, n.z, uv.x, uv.y].map(f32::to_bits)
+        };
+        let mut sums: HashMap<[u32; 8], (Vector3<f32>, Vector3<f32>)> = HashMap::new();
+        for tri in self.tris.iter() {
+            let (e1, e2) = (tri.p[1].xyz() - tri.p[0].xyz(), tri.p[2].xyz() - tri.p[0].xyz());
+            let (d1, d2) = (tri.uv[1] - tri.uv[0], tri.uv[2] - tri.uv[0]);
+            // Solve e1 = d1.x * T + d1.y * B and e2 = d2.x * T + d2.y * B,
+            // keeping the size of the triangle to weigh it
+            let det = d1.x * d2.y - d2.x * d1.y;
+            if det == 0.0 {
+                continue;
+            }
+            let tangent = (e1 * d2.y - e2 * d1.y) / det;
+            let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+            for i in 0..3 {
+                let sum = sums.entry(key(tri, i)).or_insert((Vector3::zeros(), Vector3::zeros()));
+                sum.0 += tangent;
+                sum.1 += bitangent;
+            }
+        }
+
+        for tri in self.tris.iter_mut() {
+            let face_normal = (tri.p[1].xyz() - tri.p[0].xyz()).cross(&(tri.p[2].xyz() - tri.p[0].xyz()))
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(Vector3::z);
+            for i in 0..3 {
+                let normal = if tri.n[i] == Vector3::zeros() { face_normal } else { tri.n[i] };
+                let (tangent, bitangent) = sums.get(&key(tri, i)).copied()
+                    .unwrap_or((Vector3::zeros(), Vector3::zeros()));
+                // Without texture coordinates, any direction along the surface
+                let any = if normal.x.abs() < 0.9 { Vector3::x() } else { Vector3::y() };
+                let tangent = (tangent - normal * normal.dot(&tangent))
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or_else(|| normal.cross(&any).normalize());
+                let handedness = if normal.cross(&tangent).dot(&bitangent) < 0.0 { -1.0 } else { 1.0 };
+                tri.tangent[i] = Vector4::new(tangent.x, tangent.y, tangent.z, handedness);
+            }
+        }
+    }
+
+    /// The triangles using each material of the library, as meshes of
+    /// their own, with the name of the material, `None` for the ones
+    /// before any material
+    pub fn split_by_material(self) -> Vec<(Option<String>, Mesh)> {
+        let mut starts: Vec<(Option<String>, usize)> = vec![(None, 0)];
+        starts.extend(self.material_groups.iter().map(|(name, start)| (Some(name.clone()), *start)));
+
+        let mut groups: Vec<(Option<String>, Vec<Tri>)> = Vec::new();
+        for (i, (name, start)) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(self.tris.len(), |next| next.1);
+            if *start >= end {
+                continue;
+            }
+            // A material used again adds to its first group
+            match groups.iter_mut().find(|group| group.0 == *name) {
+                Some(group) => group.1.extend_from_slice(&self.tris[*start..end]),
+                None => groups.push((name.clone(), self.tris[*start..end].to_vec()))
+            }
+        }
+        groups.into_iter().map(|(name, tris)| (name, Mesh::new(tris))).collect()
     }
 
     /// Sorts the triangles in the mesh by their average z coordinate
+    #[allow(dead_code)]
     pub fn sort(&mut self) {
         self.tris.sort_by(|a, b| {
             let dist_a = (a.p[0].z + a.p[1].z + a.p[2].z) / 3.0;
@@ -327,6 +543,37 @@ impl Mesh {
         });
     }
 
+    /// Moves the vertices so the center of the bounding box is at the origin
+    pub fn recenter(&mut self) {
+        let center = self.bounding_box().center();
+        for tri in self.tris.iter_mut() {
+            *tri += Vector4::new(-center.x, -center.y, -center.z, 0.0);
+        }
+    }
+
+    /// Axis-aligned box that contains every vertex of the mesh
+    pub fn bounding_box(&self) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for tri in self.tris.iter() {
+            for p in tri.p.iter() {
+                aabb.add_point(p.xyz());
+            }
+        }
+        aabb
+    }
+
+    /// Sphere centered in the bounding box that contains every vertex of the mesh
+    ///
+    /// Tighter than the sphere around the box itself.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        let center = self.bounding_box().center();
+        let radius = self.tris.iter()
+            .flat_map(|tri| tri.p.iter())
+            .map(|p| (p.xyz() - center).magnitude())
+            .fold(0.0, f32::max);
+        BoundingSphere::new(center, radius)
+    }
+
 }
 
 
@@ -340,8 +587,139 @@ mod tests {
         let t1 = Tri::from([ 1.0,-1.0, 1.0, 1.0, 1.0, 1.0,-1.0, 1.0, 1.0]);
         let t2 = Tri::from([-1.0,-1.0, 1.0,-1.0, 1.0, 1.0,-1.0, 1.0,-1.0]);
 
-        assert_eq!(t1.normal(), Vector4::new(0.0, 0.0, 1.0, 0.0));
-        assert_eq!(t2.normal(), Vector4::new(-1.0, 0.0, 0.0, 0.0));
+        assert_eq!(t1.normal(), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(t2.normal(), Vector3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_clip() {
+        let t = Tri::from([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let point = Vector3::new(0.5, 0.0, 0.0);
+
+        // One vertex left on the inside
+        let clipped = t.clip_against_plane(point, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(clipped.len(), 1);
+        assert_eq!(clipped[0].p[0], Vector4::new(0.5, 0.0, 0.0, 1.0));
+        assert_eq!(clipped[0].p[1], Vector4::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(clipped[0].p[2], Vector4::new(0.5, 0.5, 0.0, 1.0));
+        assert!(clipped[0].normal().z > 0.0);
+
+        // Two vertices left on the inside
+        let clipped = t.clip_against_plane(point, Vector3::new(-1.0, 0.0, 0.0));
+        assert_eq!(clipped.len(), 2);
+        assert!(clipped.iter().all(|tri| tri.normal().z > 0.0));
+        let area: f32 = clipped.iter().map(|tri| tri.signed_area()).sum();
+        assert!((area - 0.375).abs() < 1e-6);
+
+        assert!(t.clip_against_plane(Vector3::new(2.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn test_clip_attributes() {
+        // Cutting the triangle halfway along x interpolates normals, uvs
+        // and world positions too
+        let mut t = Tri::from([0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0, 0.0]);
+        t.uv = [Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0), Vector2::new(0.0, 1.0)];
+        t.n = [Vector3::z(), Vector3::x(), Vector3::z()];
+        t.world = [Vector3::zeros(), Vector3::new(4.0, 0.0, 0.0), Vector3::zeros()];
+        t.tangent = [Vector4::x(), Vector4::y(), Vector4::x()];
+        let clipped = t.clip_against_plane(Vector3::new(1.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0));
+        let cut = clipped.iter()
+            .flat_map(|tri| (0..3).map(move |i| (tri.p[i], tri.uv[i], tri.n[i], tri.world[i], tri.tangent[i])))
+            .find(|(p, _, _, _, _)| (p.x - 1.0).abs() < 1e-6 && p.y.abs() < 1e-6)
+            .unwrap();
+        assert!((cut.1 - Vector2::new(0.5, 0.0)).magnitude() < 1e-6);
+        assert!((cut.2 - Vector3::new(0.5, 0.0, 0.5)).magnitude() < 1e-6);
+        assert!((cut.3 - Vector3::new(2.0, 0.0, 0.0)).magnitude() < 1e-6);
+        assert!((cut.4 - Vector4::new(0.5, 0.5, 0.0, 0.0)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn test_tangents() {
+        // A square in the xy plane, facing z, with u along x and v along
+        // y, then with v mirrored, and a triangle without uvs
+        let square = |v_sign: f32| {
+            let mut tris = vec![
+                Tri::from([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0]),
+                Tri::from([0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0])
+            ];
+            for tri in tris.iter_mut() {
+                tri.uv = tri.p.map(|p| Vector2::new(p.x, p.y * v_sign));
+                tri.n = [Vector3::z(); 3];
+            }
+            tris
+        };
+        let mut mesh = Mesh::new(square(1.0).into_iter().chain(square(-1.0)).collect());
+        mesh.tris.push(Tri::from([0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]));
+        mesh.compute_tangents();
+
+        assert!(mesh.tris[..2].iter().flat_map(|tri| tri.tangent).all(|t| t == Vector4::new(1.0, 0.0, 0.0, 1.0)));
+        assert!(mesh.tris[2..4].iter().flat_map(|tri| tri.tangent).all(|t| t == Vector4::new(1.0, 0.0, 0.0, -1.0)));
+        let tri = mesh.tris[4];
+        assert!(tri.tangent.iter().all(|t| (t.xyz().magnitude() - 1.0).abs() < 1e-6 && t.xyz().dot(&tri.normal()).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_split_by_material() {
+        let tri = Tri::from([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let mut mesh = Mesh::new(vec![tri; 5]);
+        mesh.material_groups = vec![("wood".to_string(), 1), ("metal".to_string(), 3), ("wood".to_string(), 4)];
+        let groups: Vec<(Option<String>, usize)> = mesh.split_by_material().into_iter()
+            .map(|(name, mesh)| (name, mesh.tris.len()))
+            .collect();
+        assert_eq!(groups, vec![(None, 1), (Some("wood".to_string()), 3), (Some("metal".to_string()), 1)]);
+    }
+
+    #[test]
+    fn test_parse_obj() {
+        let mesh = Mesh::parse_obj("# A triangle\nv 0 0 0\nv 1 0 0\nv 0 1.5 0\nf 1 2 3\n").unwrap();
+        assert_eq!(mesh.tris.len(), 1);
+        assert_eq!(mesh.tris[0].p[2], Vector4::new(0.0, 1.5, 0.0, 1.0));
+
+        // Bad lines are errors pointing to them
+        assert_eq!(Mesh::parse_obj("v 0 0 0\nv 1 x 0\n").err().unwrap(), "line 2: invalid number \"x\"");
+        assert_eq!(Mesh::parse_obj("v 0 0 0\nf 1 1 2\n").err().unwrap(), "line 2: no vertex 2");
+    }
+
+    #[test]
+    fn test_obj_indices() {
+        let lines = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nvn 0 0 2\nvn 0 1 0\n";
+        // Normals without texture coordinates
+        let mesh = Mesh::parse_obj(&format!("{}f 1//1 2//1 3//2\n", lines)).unwrap();
+        assert_eq!(mesh.tris[0].n, [Vector3::z(), Vector3::z(), Vector3::y()]);
+        assert_eq!(mesh.tris[0].uv, [Vector2::zeros(); 3]);
+
+        // Negative indices count back from the last one so far
+        let mesh = Mesh::parse_obj(&format!("{}f -3/-1/-2 -2/1/-2 -1/-1/-1\n", lines)).unwrap();
+        assert_eq!(mesh.tris[0].p[0], Vector4::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(mesh.tris[0].p[2], Vector4::new(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(mesh.tris[0].uv[1], Vector2::new(0.5, 0.5));
+        assert_eq!(mesh.tris[0].n, [Vector3::z(), Vector3::z(), Vector3::y()]);
+
+        // Out of range, zero and garbage indices are errors
+        let error = |face: &str| Mesh::parse_obj(&format!("{}{}\n", lines, face)).err().unwrap();
+        assert_eq!(error("f 1 2 4"), "line 7: no vertex 4");
+        assert_eq!(error("f 1 2 -4"), "line 7: no vertex -4");
+        assert_eq!(error("f 0 1 2"), "line 7: no vertex 0");
+        assert_eq!(error("f 1/2 2/1 3/1"), "line 7: no texture coordinate 2");
+        assert_eq!(error("f 1//3 2//1 3//1"), "line 7: no normal 3");
+        assert_eq!(error("f 1 x 3"), "line 7: invalid vertex index \"x\"");
+        assert_eq!(error("f /1 2 3"), "line 7: missing vertex");
+    }
+
+    #[test]
+    fn test_mesh_bounds() {
+        let mesh = Mesh::new(vec![
+            Tri::from([ 1.0,-1.0, 1.0, 1.0, 1.0, 1.0,-1.0, 1.0, 1.0]),
+            Tri::from([-1.0,-1.0, 1.0,-1.0, 1.0, 1.0,-1.0, 1.0,-1.0])
+        ]);
+        let aabb = mesh.bounding_box();
+        assert_eq!(aabb.min, Vector3::new(-1.0, -1.0, -1.0));
+        assert_eq!(aabb.max, Vector3::new(1.0, 1.0, 1.0));
+
+        let sphere = mesh.bounding_sphere();
+        assert_eq!(sphere.center, Vector3::new(0.0, 0.0, 0.0));
+        assert!((sphere.radius - 3.0_f32.sqrt()).abs() < 1e-6);
     }
 
     // Draw a triangle
@@ -0,0 +1,636 @@
+/*!
+# raster.rs
+
+Rasterization of screen space triangles into a `Framebuffer`.
+
+Pixels are tested against the three edges of the triangle at their
+centers (half-space rasterization), with a top-left fill rule so
+triangles sharing an edge never draw a pixel twice or leave gaps.
+The edges are evaluated for blocks of 2x2 pixels at once with SIMD.
+The depth is interpolated across the triangle and tested against
+the depth buffer, so triangles can be drawn in any order.
+
+Triangles can be filled with a single color, or shaded per pixel from
+their vertex attributes. Transparent triangles are blended over the
+pixels instead, depth tested without writing their depth. For the
+attributes to be interpolated with the perspective, the w of each
+vertex holds 1/w from before the divide.
+Pixels are shaded in 2x2 quads aligned to even pixels, the change of
+the weights across the quad giving how fast the attributes change on
+screen, to filter textures with.
+
+Lines are anti-aliased with Xiaolin Wu's algorithm, blending each pixel
+with the line by how much of it the line covers.
+*/
+
+use std::mem::swap;
+use std::ops::{Add, Mul};
+
+use na::{Vector3, Vector4};
+use sdl2::pixels::Color;
+
+use crate::antialiasing;
+use crate::framebuffer::Framebuffer;
+use crate::material::BlendMode;
+use crate::polygons::Tri;
+use crate::simd::F32x4;
+use crate::transparency::{self, Accumulation};
+
+/// Where a pixel is in a triangle being shaded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fragment {
+    /// Weights of the vertices at the center of the pixel, corrected for
+    /// the perspective
+    pub weights: [f32; 3],
+    /// Change of the weights from the left to the right column of the 2x2
+    /// quad of pixels it is in
+    pub dx: [f32; 3],
+    /// Change of the weights from the top to the bottom row of the quad
+    pub dy: [f32; 3]
+}
+
+impl Fragment {
+    /// Value at the pixel of an attribute of the vertices
+    pub fn interpolate<T: Copy + Add<Output = T> + Mul<f32, Output = T>>(&self, values: &[T; 3]) -> T {
+        values[0] * self.weights[0] + values[1] * self.weights[1] + values[2] * self.weights[2]
+    }
+
+    /// Change of an attribute of the vertices from one pixel to the next,
+    /// along x and along y
+    pub fn derivatives<T: Copy + Add<Output = T> + Mul<f32, Output = T>>(&self, values: &[T; 3]) -> (T, T) {
+        let change = |d: [f32; 3]| values[0] * d[0] + values[1] * d[1] + values[2] * d[2];
+        (change(self.dx), change(self.dy))
+    }
+}
+
+/// Edge function: twice the signed area of the triangle `a, b, (x, y)`
+///
+/// Positive when the point is to the right of the edge on a y-down screen.
+fn edge(a: &Vector4<f32>, b: &Vector4<f32>, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// Whether the edge from `a` to `b` is a top or left edge
+/// of a clockwise triangle on a y-down screen
+fn is_top_left(a: &Vector4<f32>, b: &Vector4<f32>) -> bool {
+    (a.y == b.y && b.x > a.x) || b.y < a.y
+}
+
+/// Draws an anti-aliased line `width` pixels wide between two points in
+/// screen space
+///
+/// With a `depth_offset` the line is depth tested, pulled towards the
+/// camera by that fraction of its distance so it wins over the triangles
+/// it lies on. Without one it is drawn over everything.
+pub fn draw_line(
+    framebuffer: &mut Framebuffer,
+    a: &Vector4<f32>,
+    b: &Vector4<f32>,
+    color: Color,
+    width: f32,
+    depth_offset: Option<f32>
+) {
+    let size = (framebuffer.width, framebuffer.height);
+    line_coverage(size, a, b, width, |x, y, z, coverage| {
+        if passes_depth(framebuffer, x, y, z, depth_offset) {
+            framebuffer.blend(x, y, color, coverage);
+        }
+    });
+}
+
+/// Calls `visit` with the x, y, depth and coverage of every pixel of a
+/// screen of the given size that a line `width` pixels wide covers
+///
+/// Xiaolin Wu's algorithm: the line is walked a pixel at a time along its
+/// longest axis, and the pixels across it are covered by how close their
+/// centers are to it. Like in a line one pixel wide, the ends reach half
+/// a pixel past the endpoints, covering a fraction of their pixels so the
+/// line moves smoothly with its endpoints.
+pub fn line_coverage<F: FnMut(usize, usize, f32, f32)>(
+    size: (usize, usize),
+    a: &Vector4<f32>,
+    b: &Vector4<f32>,
+    width: f32,
+    mut visit: F
+) {
+    // Walk along x, swapping the axes of steep lines
+    let steep = (b.y - a.y).abs() > (b.x - a.x).abs();
+    let (mut a, mut b, major_size, minor_size) = if steep {
+        (Vector3::new(a.y, a.x, a.z), Vector3::new(b.y, b.x, b.z), size.1, size.0)
+    } else {
+        (a.xyz(), b.xyz(), size.0, size.1)
+    };
+    if a.x > b.x {
+        swap(&mut a, &mut b);
+    }
+    let length = b.x - a.x;
+    let (gradient, depth_gradient) = if length > 0.0 {
+        ((b.y - a.y) / length, (b.z - a.z) / length)
+    } else {
+        (0.0, 0.0)
+    };
+    // Half the thickness across the walked axis, for the line to keep its
+    // width perpendicular to it at any angle
+    let half = width * 0.5 * (1.0 + gradient * gradient).sqrt();
+
+    let (start, end) = (a.x - 0.5, b.x + 0.5);
+    let first = start.floor().max(0.0) as i64;
+    let last = (end.ceil() as i64 - 1).min(major_size as i64 - 1);
+    for column in first..=last {
+        // Part of the column the line reaches into, less than 1 at its ends
+        let reach = (end.min(column as f32 + 1.0) - start.max(column as f32)).clamp(0.0, 1.0);
+        let x = column as f32 + 0.5;
+        let center = a.y + gradient * (x - a.x);
+        let z = a.z + depth_gradient * (x.clamp(a.x, b.x) - a.x);
+
+        let top = (center - half - 0.5).floor().max(0.0) as i64;
+        let bottom = ((center + half + 0.5).ceil() as i64).min(minor_size as i64 - 1);
+        for row in top..=bottom {
+            let distance = (row as f32 + 0.5 - center).abs();
+            let coverage = (half + 0.5 - distance).clamp(0.0, 1.0) * reach;
+            if coverage > 0.0 {
+                let (row, column) = (row as usize, column as usize);
+                if steep {
+                    visit(row, column, z, coverage);
+                } else {
+                    visit(column, row, z, coverage);
+                }
+            }
+        }
+    }
+}
+
+/// Draws a square point of `size` pixels centered on the vertex, over everything
+pub fn draw_point(framebuffer: &mut Framebuffer, p: &Vector4<f32>, color: Color, size: usize) {
+    let half = (size as f32 - 1.0) * 0.5;
+    for dy in 0..size {
+        for dx in 0..size {
+            plot(framebuffer, p.x - half + dx as f32, p.y - half + dy as f32, p.z, color, None);
+        }
+    }
+}
+
+/// Sets the pixel the point falls in, if it is on screen and passes
+/// the optional depth test
+fn plot(framebuffer: &mut Framebuffer, x: f32, y: f32, z: f32, color: Color, depth_offset: Option<f32>) {
+    if x < 0.0 || y < 0.0 || x >= framebuffer.width as f32 || y >= framebuffer.height as f32 {
+        return;
+    }
+    let (x, y) = (x as usize, y as usize);
+    if passes_depth(framebuffer, x, y, z, depth_offset) {
+        framebuffer.set(x, y, color);
+    }
+}
+
+/// Whether a pixel at depth `z`, pulled towards the camera by the offset,
+/// is in front of what was drawn there, always true without an offset
+fn passes_depth(framebuffer: &Framebuffer, x: usize, y: usize, z: f32, depth_offset: Option<f32>) -> bool {
+    match depth_offset {
+        // The depth is not linear, 1 - z shrinks with the distance like the depth steps do
+        Some(offset) => z - offset * (1.0 - z) <= framebuffer.depth[y * framebuffer.width + x],
+        None => true
+    }
+}
+
+/// Fills a triangle in screen space with its color, with depth testing
+///
+/// The x and y of the vertices are in pixels, and z is the depth.
+#[allow(dead_code)]
+pub fn fill_triangle(framebuffer: &mut Framebuffer, tri: &Tri) {
+    let color = tri.c.unwrap_or(Color::GREEN);
+    fill_triangle_with(framebuffer, tri, |_| color);
+}
+
+/// Fills a triangle in screen space, with depth testing, coloring each
+/// pixel with `shade`
+///
+/// `shade` gets the weight of each vertex at the pixel, corrected for
+/// the perspective with the 1/w kept in the vertices, to interpolate
+/// their attributes with, and how they change across its quad. With
+/// multisampling, the coverage and depth are tested for each sample, but
+/// each pixel is shaded once.
+pub fn fill_triangle_with<F: FnMut(Fragment) -> Color>(framebuffer: &mut Framebuffer, tri: &Tri, shade: F) {
+    draw_triangle(framebuffer, tri, None, shade);
+}
+
+/// Blends a triangle in screen space over what is drawn, coloring each
+/// pixel with `shade` like `fill_triangle_with`
+///
+/// The alpha of the color of the triangle is its opacity. It is depth
+/// tested, but doesn't write its depth, so what is behind it and drawn
+/// later still shows through.
+pub fn blend_triangle_with<F: FnMut(Fragment) -> Color>(
+    framebuffer: &mut Framebuffer,
+    tri: &Tri,
+    mode: BlendMode,
+    shade: F
+) {
+    draw_triangle(framebuffer, tri, Some(mode), shade);
+}
+
+/// Adds a triangle in screen space to the sums of weighted blended
+/// transparency, coloring each pixel with `shade` like `fill_triangle_with`
+///
+/// It is depth tested once per pixel, against the resolved depth of the
+/// framebuffer, which the accumulation has the size of.
+pub fn accumulate_triangle_with<F: FnMut(Fragment) -> Color>(
+    framebuffer: &mut Framebuffer,
+    accumulation: &mut Accumulation,
+    tri: &Tri,
+    mut shade: F
+) {
+    let origin = framebuffer.origin;
+    let region = (origin.0, origin.1, origin.0 + framebuffer.width, origin.1 + framebuffer.height);
+    let alpha = opacity(tri);
+    let Framebuffer { depth, overdraw, .. } = framebuffer;
+
+    cover_triangle(region, tri, antialiasing::msaa_pattern(1), depth, false, |i, _, fragment| {
+        overdraw[i] += 1;
+        // Distance to the camera, from the 1/w of the vertices
+        let distance: f32 = (0..3).map(|k| fragment.weights[k] / tri.p[k].w).sum();
+        accumulation.add(i, shade(fragment), alpha, distance);
+    });
+}
+
+/// Fills or, with a blend mode, blends the triangle into the pixels,
+/// or the samples with multisampling
+fn draw_triangle<F: FnMut(Fragment) -> Color>(
+    framebuffer: &mut Framebuffer,
+    tri: &Tri,
+    blend: Option<BlendMode>,
+    mut shade: F
+) {
+    let (origin, samples) = (framebuffer.origin, framebuffer.samples);
+    let region = (origin.0, origin.1, origin.0 + framebuffer.width, origin.1 + framebuffer.height);
+    let pattern = antialiasing::msaa_pattern(samples);
+    let alpha = opacity(tri);
+    let Framebuffer { pixels, depth, overdraw, sample_pixels, sample_depth, .. } = framebuffer;
+    let put = |pixel: &mut [u8], color: Color| {
+        let value = match blend {
+            Some(mode) => transparency::blend(mode, [pixel[0], pixel[1], pixel[2]], color, alpha),
+            None => [color.r, color.g, color.b]
+        };
+        pixel.copy_from_slice(&value);
+    };
+
+    if samples == 1 {
+        cover_triangle(region, tri, pattern, depth, blend.is_none(), |i, _, fragment| {
+            overdraw[i] += 1;
+            put(&mut pixels[i * 3..i * 3 + 3], shade(fragment));
+        });
+    } else {
+        cover_triangle(region, tri, pattern, sample_depth, blend.is_none(), |i, mask, fragment| {
+            overdraw[i] += 1;
+            let color = shade(fragment);
+            for sample in (0..samples).filter(|sample| mask & (1 << sample) != 0) {
+                let j = (i * samples + sample) * 3;
+                put(&mut sample_pixels[j..j + 3], color);
+            }
+        });
+    }
+}
+
+/// Opacity of the triangle, from the alpha of its color
+fn opacity(tri: &Tri) -> f32 {
+    tri.c.map_or(1.0, |color| color.a as f32 / 255.0)
+}
+
+/// First pixel and the pixel past the last of the bounding box of the
+/// triangle, clamped to the area from `(min_x, min_y)` to `(max_x, max_y)`
+fn bounds_in(
+    p: &[Vector4<f32>; 3],
+    (min_x, min_y, max_x, max_y): (usize, usize, usize, usize)
+) -> ((usize, usize), (usize, usize)) {
+    let min = |axis: fn(&Vector4<f32>) -> f32| p.iter().map(axis).fold(f32::INFINITY, f32::min);
+    let max = |axis: fn(&Vector4<f32>) -> f32| p.iter().map(axis).fold(f32::NEG_INFINITY, f32::max);
+    (
+        (min(|v| v.x).floor().max(min_x as f32) as usize, min(|v| v.y).floor().max(min_y as f32) as usize),
+        ((max(|v| v.x).ceil() as usize).min(max_x), (max(|v| v.y).ceil() as usize).min(max_y))
+    )
+}
+
+/// Pixels the triangle can cover on a screen of the given size, from the
+/// first one to the one past the last on each axis
+pub fn pixel_bounds(tri: &Tri, width: usize, height: usize) -> ((usize, usize), (usize, usize)) {
+    bounds_in(&tri.p, (0, 0, width, height))
+}
+
+/// Tests the samples of every pixel under the triangle, at the positions
+/// of the pattern, against its edges and the depth buffer of the samples,
+/// writing the depth of the samples that pass if `depth_write` is set
+///
+/// Only the pixels in `region`, from its top left corner to the one past
+/// its bottom right corner, are drawn, the buffers covering just them.
+/// `write` gets the index of each pixel the triangle shows in, a mask of
+/// the samples it covers, and the perspective correct weights of the
+/// vertices at the center of the pixel. Their changes across its quad
+/// are taken between the centers of the quad, even those outside the
+/// triangle, extending the weights past its edges.
+fn cover_triangle<F: FnMut(usize, u32, Fragment)>(
+    region: (usize, usize, usize, usize),
+    tri: &Tri,
+    pattern: &[(f32, f32)],
+    depth: &mut [f32],
+    depth_write: bool,
+    mut write: F
+) {
+    // Make the triangle clockwise, so the inside is positive on every edge,
+    // and remember where each vertex came from to give back their weights
+    let mut p = tri.p;
+    let mut order = [0, 1, 2];
+    let mut area = edge(&p[0], &p[1], p[2].x, p[2].y);
+    if area < 0.0 {
+        p.swap(1, 2);
+        order.swap(1, 2);
+        area = -area;
+    }
+    if area == 0.0 {
+        return;
+    }
+
+    // Bounding box of the triangle, clamped to the area
+    let ((min_x, min_y), (max_x, max_y)) = bounds_in(&p, region);
+    let (left, top, width) = (region.0, region.1, region.2 - region.0);
+
+    // Edges opposite to each vertex, so their weights are barycentric coordinates
+    let edges = [(1, 2), (2, 0), (0, 1)];
+    let top_left = edges.map(|(a, b)| is_top_left(&p[a], &p[b]));
+    let weights_at = |px: f32, py: f32| edges.map(|(a, b)| edge(&p[a], &p[b], px, py));
+
+    // Weights in 3D, interpolating 1/w on screen
+    let perspective = |weights: [f32; 3]| {
+        let inverse_w = [weights[0] * p[0].w, weights[1] * p[1].w, weights[2] * p[2].w];
+        let total = inverse_w[0] + inverse_w[1] + inverse_w[2];
+        let mut perspective = [0.0; 3];
+        for (k, &vertex) in order.iter().enumerate() {
+            perspective[vertex] = inverse_w[k] / total;
+        }
+        perspective
+    };
+    // The fragment at a pixel, from the weights at the top left, top right
+    // and bottom left pixels of its quad
+    let fragment = |weights: [f32; 3], quad: [[f32; 3]; 3]| {
+        let change = |to: [f32; 3]| [to[0] - quad[0][0], to[1] - quad[0][1], to[2] - quad[0][2]];
+        Fragment { weights, dx: change(quad[1]), dy: change(quad[2]) }
+    };
+
+    if pattern.len() == 1 {
+        // One sample at the center of each pixel, tested 2x2 pixels at a time
+        let edge_x4 = edges.map(|(a, b)| (
+            F32x4::splat(p[b].x - p[a].x), F32x4::splat(p[a].y),
+            F32x4::splat(p[b].y - p[a].y), F32x4::splat(p[a].x)
+        ));
+        let (zero, area_x4) = (F32x4::splat(0.0), F32x4::splat(area));
+        let z_x4 = p.map(|v| F32x4::splat(v.z));
+
+        // The blocks are the quads, starting on even pixels
+        for y in (min_y & !1..max_y).step_by(2) {
+            let ys = F32x4::new(y as f32 + 0.5, y as f32 + 0.5, (y + 1) as f32 + 0.5, (y + 1) as f32 + 0.5);
+            for x in (min_x & !1..max_x).step_by(2) {
+                let xs = F32x4::new(x as f32 + 0.5, (x + 1) as f32 + 0.5, x as f32 + 0.5, (x + 1) as f32 + 0.5);
+
+                // Lanes 1 and 3 are the right column, 2 and 3 the bottom row
+                let mut mask = 0b1111;
+                if x < min_x {
+                    mask &= 0b1010;
+                }
+                if y < min_y {
+                    mask &= 0b1100;
+                }
+                if x + 1 >= max_x {
+                    mask &= 0b0101;
+                }
+                if y + 1 >= max_y {
+                    mask &= 0b0011;
+                }
+                let weights = edge_x4.map(|(dx, ay, dy, ax)| dx * (ys - ay) - dy * (xs - ax));
+                for (w, &top_left) in weights.iter().zip(top_left.iter()) {
+                    mask &= w.gt_mask(zero) | if top_left { w.eq_mask(zero) } else { 0 };
+                }
+                if mask == 0 {
+                    continue;
+                }
+
+                let z = ((weights[0] * z_x4[0] + weights[1] * z_x4[1] + weights[2] * z_x4[2]) / area_x4).to_array();
+                let weights = weights.map(F32x4::to_array);
+                let lanes = [0, 1, 2, 3].map(|lane| perspective([weights[0][lane], weights[1][lane], weights[2][lane]]));
+                let quad = [lanes[0], lanes[1], lanes[2]];
+                for lane in (0..4).filter(|lane| mask & (1 << lane) != 0) {
+                    let i = (y + lane / 2 - top) * width + x + lane % 2 - left;
+                    if z[lane] < depth[i] {
+                        if depth_write {
+                            depth[i] = z[lane];
+                        }
+                        write(i, 1, fragment(lanes[lane], quad));
+                    }
+                }
+            }
+        }
+        return;
+    }
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let i = (y - top) * width + x - left;
+
+            let mut mask = 0;
+            for (sample, &(sx, sy)) in pattern.iter().enumerate() {
+                let weights = weights_at(x as f32 + sx, y as f32 + sy);
+                let outside = (0..3).any(|e| weights[e] < 0.0 || (weights[e] == 0.0 && !top_left[e]));
+                if outside {
+                    continue;
+                }
+
+                let z = (weights[0] * p[0].z + weights[1] * p[1].z + weights[2] * p[2].z) / area;
+                let j = i * pattern.len() + sample;
+                if z < depth[j] {
+                    if depth_write {
+                        depth[j] = z;
+                    }
+                    mask |= 1 << sample;
+                }
+            }
+            if mask == 0 {
+                continue;
+            }
+            let at = |x: usize, y: usize| perspective(weights_at(x as f32 + 0.5, y as f32 + 0.5));
+            let (qx, qy) = (x & !1, y & !1);
+            write(i, mask, fragment(at(x, y), [at(qx, qy), at(qx + 1, qy), at(qx, qy + 1)]));
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_edge() {
+        // Two triangles covering a square, sharing the diagonal
+        let mut framebuffer = Framebuffer::new(8, 8);
+        let a = Tri::from([0.0, 0.0, 0.5, 8.0, 0.0, 0.5, 0.0, 8.0, 0.5]);
+        let b = Tri::from([8.0, 0.0, 0.5, 8.0, 8.0, 0.5, 0.0, 8.0, 0.5]);
+
+        // Count the writes of each pixel through the depth buffer
+        let mut coverage = [0; 64];
+        for tri in [a, b].iter() {
+            framebuffer.depth.fill(f32::INFINITY);
+            fill_triangle(&mut framebuffer, tri);
+            for (i, depth) in framebuffer.depth.iter().enumerate() {
+                if *depth < f32::INFINITY {
+                    coverage[i] += 1;
+                }
+            }
+        }
+        assert!(coverage.iter().all(|&c| c == 1));
+    }
+
+    #[test]
+    fn test_blocks() {
+        // The pixels tested 2x2 at a time are the ones whose centers are
+        // inside the triangle, on an odd sized screen
+        let mut framebuffer = Framebuffer::new(15, 11);
+        let tri = Tri::from([1.3, 0.2, 0.5, 13.7, 4.1, 0.5, 3.9, 10.6, 0.5]);
+        fill_triangle(&mut framebuffer, &tri);
+        for y in 0..11 {
+            for x in 0..15 {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let inside = [(0, 1), (1, 2), (2, 0)].iter()
+                    .all(|&(a, b)| edge(&tri.p[a], &tri.p[b], px, py) > 0.0);
+                assert_eq!(framebuffer.depth[y * 15 + x].is_finite(), inside);
+            }
+        }
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let mut near = Tri::from([0.0, 0.0, 0.2, 4.0, 0.0, 0.2, 0.0, 4.0, 0.2]);
+        let mut far = Tri::from([0.0, 0.0, 0.8, 4.0, 0.0, 0.8, 0.0, 4.0, 0.8]);
+        near.c = Some(Color::RED);
+        far.c = Some(Color::BLUE);
+
+        fill_triangle(&mut framebuffer, &near);
+        fill_triangle(&mut framebuffer, &far);
+        assert_eq!(framebuffer.get(0, 0), Color::RED);
+        assert!((framebuffer.depth[0] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_blend_triangle() {
+        // A half transparent triangle in front blends over the one behind
+        // without hiding it, and one behind is hidden
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let mut back = Tri::from([0.0, 0.0, 0.5, 4.0, 0.0, 0.5, 0.0, 4.0, 0.5]);
+        back.c = Some(Color::RED);
+        fill_triangle(&mut framebuffer, &back);
+
+        let mut glass = Tri::from([0.0, 0.0, 0.2, 4.0, 0.0, 0.2, 0.0, 4.0, 0.2]);
+        glass.c = Some(Color::RGBA(0, 0, 255, 128));
+        blend_triangle_with(&mut framebuffer, &glass, BlendMode::Alpha, |_| glass.c.unwrap());
+        assert_eq!(framebuffer.get(0, 0), Color::RGB(127, 0, 128));
+        assert_eq!(framebuffer.depth[0], 0.5);
+
+        let mut hidden = Tri::from([0.0, 0.0, 0.8, 4.0, 0.0, 0.8, 0.0, 4.0, 0.8]);
+        hidden.c = Some(Color::RGBA(0, 255, 0, 128));
+        blend_triangle_with(&mut framebuffer, &hidden, BlendMode::Additive, |_| hidden.c.unwrap());
+        assert_eq!(framebuffer.get(0, 0), Color::RGB(127, 0, 128));
+        assert_eq!(framebuffer.overdraw[0], 2);
+    }
+
+    #[test]
+    fn test_perspective_weights() {
+        // The second vertex is 3 times further away than the others,
+        // so it weighs less on screen than its screen area says
+        // Only the first row, to know which pixel each shading is for
+        let mut framebuffer = Framebuffer::new(8, 1);
+        let mut tri = Tri::from([0.0, 0.0, 0.5, 8.0, 0.0, 0.5, 0.0, 8.0, 0.5]);
+        tri.p[1].w = 1.0 / 3.0;
+        let mut weights = Vec::new();
+        fill_triangle_with(&mut framebuffer, &tri, |fragment| {
+            weights.push(fragment.weights);
+            Color::WHITE
+        });
+        assert!(weights.iter().all(|w| (w[0] + w[1] + w[2] - 1.0).abs() < 1e-5));
+
+        // At the pixel (3, 0) the screen weight of the second vertex is 7/16
+        let w = weights[3];
+        let expected = (7.0 / 16.0 / 3.0) / (9.0 / 16.0 + 7.0 / 16.0 / 3.0);
+        assert!((w[1] - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_quad_derivatives() {
+        // Without perspective the weights change by the same amount every
+        // pixel, the same with one or several samples, and the quads line
+        // up however far the triangle starts into one
+        let tri = Tri::from([1.0, 1.0, 0.5, 9.0, 1.0, 0.5, 1.0, 9.0, 0.5]);
+        let uv = [Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)];
+        for samples in [1, 4] {
+            let mut framebuffer = Framebuffer::new(10, 10);
+            framebuffer.set_samples(samples);
+            let mut fragments = Vec::new();
+            fill_triangle_with(&mut framebuffer, &tri, |fragment| {
+                fragments.push(fragment);
+                Color::WHITE
+            });
+            assert!(!fragments.is_empty());
+            for fragment in fragments {
+                let (dx, dy) = fragment.derivatives(&uv);
+                assert!((dx - Vector3::new(0.125, 0.0, 0.0)).norm() < 1e-5);
+                assert!((dy - Vector3::new(0.0, 0.125, 0.0)).norm() < 1e-5);
+            }
+        }
+
+        // Further away vertices change faster on screen
+        let mut far = tri;
+        far.p[1].w = 0.25;
+        let mut framebuffer = Framebuffer::new(10, 10);
+        let mut changes = Vec::new();
+        fill_triangle_with(&mut framebuffer, &far, |fragment| {
+            changes.push(fragment.derivatives(&uv).0.x);
+            Color::WHITE
+        });
+        assert!(changes.first() < changes.last());
+    }
+
+    #[test]
+    fn test_line_depth_offset() {
+        // A line lying on a triangle shows over it only with the offset
+        let mut framebuffer = Framebuffer::new(8, 8);
+        let mut tri = Tri::from([0.0, 0.0, 0.9, 8.0, 0.0, 0.9, 0.0, 8.0, 0.9]);
+        tri.c = Some(Color::RED);
+        fill_triangle(&mut framebuffer, &tri);
+
+        let (a, b) = (Vector4::new(0.5, 0.5, 0.9, 1.0), Vector4::new(6.5, 0.5, 0.9001, 1.0));
+        draw_line(&mut framebuffer, &a, &b, Color::BLUE, 1.0, Some(0.0));
+        assert_eq!(framebuffer.get(6, 0), Color::RED);
+        draw_line(&mut framebuffer, &a, &b, Color::BLUE, 1.0, Some(0.01));
+        assert!((0..7).all(|x| framebuffer.get(x, 0) == Color::BLUE));
+    }
+
+    #[test]
+    fn test_line_coverage() {
+        let coverage = |a: Vector4<f32>, b: Vector4<f32>, width: f32| {
+            let mut pixels = Vec::new();
+            line_coverage((8, 8), &a, &b, width, |x, y, _, c| pixels.push((x, y, c)));
+            pixels
+        };
+
+        // Between two rows, the line is shared by how close it is to each,
+        // and its ends only reach into half of their first and last pixels
+        let pixels = coverage(Vector4::new(1.0, 2.25, 0.0, 1.0), Vector4::new(3.0, 2.25, 0.0, 1.0), 1.0);
+        assert_eq!(pixels, vec![
+            (0, 1, 0.125), (0, 2, 0.375),
+            (1, 1, 0.25), (1, 2, 0.75),
+            (2, 1, 0.25), (2, 2, 0.75),
+            (3, 1, 0.125), (3, 2, 0.375)
+        ]);
+
+        // Three pixels wide, steep lines walk along y, and nothing is off screen
+        let pixels = coverage(Vector4::new(0.5, -4.0, 0.0, 1.0), Vector4::new(0.5, 4.5, 0.0, 1.0), 3.0);
+        assert!(pixels.iter().all(|&(x, y, c)| x <= 1 && y <= 4 && c == 1.0));
+        assert_eq!(pixels.len(), 10);
+    }
+}
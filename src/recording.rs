@@ -0,0 +1,192 @@
+/*!
+# recording.rs
+
+Records the frames shown in the window, for bug reports and demos.
+
+Frames are taken straight from the `Framebuffer`, and saved either as
+numbered PNG images, an animated GIF or an uncompressed Y4M video
+(YUV 4:4:4), which most video tools can read and encode further.
+
+GIF frames last a whole number of hundredths of a second, and viewers
+show delays under 2 as 10, so the delays follow the time of the frames
+to the closest hundredth, and frames coming less than 2 hundredths after
+the last one shown are left out.
+*/
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+use crate::framebuffer::Framebuffer;
+use crate::screenshot;
+
+/// Shortest delay of a GIF frame viewers respect, in hundredths of a second
+const MIN_GIF_DELAY: u64 = 2;
+
+/// How the recorded frames are saved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// A directory of numbered PNG images
+    Frames,
+    Gif,
+    Y4m
+}
+
+/// Where the frames go
+enum Sink {
+    Frames(String),
+    Gif(gif::Encoder<BufWriter<File>>),
+    Y4m(BufWriter<File>)
+}
+
+/// A recording in progress
+pub struct Recorder {
+    /// Directory of the frames, or file of the animation
+    pub path: String,
+    pub frames: usize,
+    width: usize,
+    height: usize,
+    fps: u64,
+    /// Hundredths of a second of the GIF frames written so far
+    gif_time: u64,
+    sink: Sink
+}
+
+impl Recorder {
+    /// Starts a recording in the directory, named after the current time
+    pub fn start(directory: &str, format: RecordFormat, width: usize, height: usize, fps: u64) -> Result<Self, String> {
+        fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory, e))?;
+        let name = format!("{}/recording_{}", directory.trim_end_matches('/'), screenshot::timestamp(std::time::SystemTime::now()));
+        let create = |path: &str| File::create(path)
+            .map(BufWriter::new)
+            .map_err(|e| format!("{}: {}", path, e));
+
+        let (path, sink) = match format {
+            RecordFormat::Frames => {
+                fs::create_dir_all(&name).map_err(|e| format!("{}: {}", name, e))?;
+                (name.clone(), Sink::Frames(name))
+            },
+            RecordFormat::Gif => {
+                let path = format!("{}.gif", name);
+                if width > u16::MAX as usize || height > u16::MAX as usize {
+                    return Err(format!("{}: {}x{} is too large for a GIF", path, width, height));
+                }
+                let mut encoder = gif::Encoder::new(create(&path)?, width as u16, height as u16, &[])
+                    .map_err(|e| format!("{}: {}", path, e))?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| format!("{}: {}", path, e))?;
+                (path, Sink::Gif(encoder))
+            },
+            RecordFormat::Y4m => {
+                let path = format!("{}.y4m", name);
+                let mut file = create(&path)?;
+                writeln!(file, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", width, height, fps)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+                (path, Sink::Y4m(file))
+            }
+        };
+        Ok(Self { path, frames: 0, width, height, fps, gif_time: 0, sink })
+    }
+
+    /// Adds the frame in the framebuffer, which has to keep the size
+    /// the recording was started with
+    pub fn add_frame(&mut self, framebuffer: &Framebuffer) -> Result<(), String> {
+        if (framebuffer.width, framebuffer.height) != (self.width, self.height) {
+            return Err(format!("{}: the frame size changed while recording", self.path));
+        }
+        match &mut self.sink {
+            Sink::Frames(directory) => {
+                framebuffer.save(&format!("{}/frame_{:05}.png", directory, self.frames))?;
+            },
+            Sink::Gif(encoder) => {
+                if let Some(delay) = gif_delay(self.frames, self.fps, self.gif_time) {
+                    let mut frame = gif::Frame::from_rgb_speed(
+                        self.width as u16, self.height as u16, &framebuffer.pixels, 10
+                    );
+                    frame.delay = delay as u16;
+                    encoder.write_frame(&frame).map_err(|e| format!("{}: {}", self.path, e))?;
+                    self.gif_time += delay;
+                }
+            },
+            Sink::Y4m(file) => {
+                file.write_all(b"FRAME\n")
+                    .and_then(|_| file.write_all(&to_yuv444(&framebuffer.pixels)))
+                    .map_err(|e| format!("{}: {}", self.path, e))?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Ends the recording, writing what is left of the file
+    pub fn finish(self) -> Result<(), String> {
+        match self.sink {
+            Sink::Frames(_) => Ok(()),
+            // The encoder writes the end of the GIF when dropped
+            Sink::Gif(encoder) => {
+                drop(encoder);
+                Ok(())
+            },
+            Sink::Y4m(mut file) => file.flush().map_err(|e| format!("{}: {}", self.path, e))
+        }
+    }
+}
+
+/// Delay of the frame of the given index in a GIF, in hundredths of a
+/// second, until the time of the next frame rounded to the closest
+/// hundredth, after the `written` hundredths of the frames before
+///
+/// `None` when that is too short for viewers, the frame is skipped and
+/// the next one lasts longer.
+fn gif_delay(index: usize, fps: u64, written: u64) -> Option<u64> {
+    let end = ((index as u64 + 1) * 200 + fps) / (2 * fps);
+    let delay = end.saturating_sub(written);
+    (delay >= MIN_GIF_DELAY).then_some(delay)
+}
+
+/// Converts RGB24 pixels to the Y, Cb and Cr planes of a Y4M frame,
+/// with the limited range BT.601 coefficients
+fn to_yuv444(pixels: &[u8]) -> Vec<u8> {
+    let count = pixels.len() / 3;
+    let mut planes = vec![0; count * 3];
+    for (i, pixel) in pixels.chunks_exact(3).enumerate() {
+        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+        let y = 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0;
+        let cb = 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0;
+        let cr = 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0;
+        planes[i] = y.round() as u8;
+        planes[count + i] = cb.round() as u8;
+        planes[2 * count + i] = cr.round() as u8;
+    }
+    planes
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yuv() {
+        // White, black and red pixels, one plane after the other
+        let planes = to_yuv444(&[255, 255, 255, 0, 0, 0, 255, 0, 0]);
+        assert_eq!(planes, vec![235, 16, 81, 128, 128, 90, 128, 128, 240]);
+    }
+
+    #[test]
+    fn test_gif_delay() {
+        let delays = |fps: u64| {
+            let mut written = 0;
+            (0..6).map(|index| {
+                let delay = gif_delay(index, fps, written);
+                written += delay.unwrap_or(0);
+                delay
+            }).collect::<Vec<_>>()
+        };
+        // Every frame on time, without drifting
+        assert_eq!(delays(30), vec![Some(3), Some(4), Some(3), Some(3), Some(4), Some(3)]);
+        assert_eq!(delays(25), vec![Some(4); 6]);
+        // Too fast for GIFs, 3 frames shown every 5 hundredths of a second
+        assert_eq!(delays(60), vec![Some(2), None, Some(3), Some(2), None, Some(3)]);
+        assert_eq!(delays(100), vec![None, Some(2), None, Some(2), None, Some(2)]);
+    }
+}
@@ -0,0 +1,922 @@
+/*!
+# render.rs
+
+The rendering pipeline, shared by the window and the headless renderer.
+
+Every triangle of every instance in the scene goes through the world,
+view and projection transforms, is culled and clipped, and lit. Then
+the triangles left are rasterized into a `Framebuffer`, filled, as
+wireframes or as points depending on the `RenderMode`.
+
+With shadows, the filled triangles are lit per pixel instead of once
+when they are projected, each light looking up its `ShadowMap`.
+With phong shading they are lit per pixel too, with the normals of their
+vertices interpolated across them. Materials with a normal map are lit
+per pixel in any case, their normal bent by the map in the space of the
+tangents of each vertex.
+
+Textured materials multiply their color per pixel with their texture,
+filtered with the change of the texture coordinates across each 2x2
+quad of pixels, see `texture`.
+
+Transparent triangles are kept apart, and blended over the opaque ones
+after these are filled, see `transparency`.
+
+Chunks of triangles are projected, and tiles of the frame filled, in
+parallel across threads, giving the same frame as a single thread.
+
+To see why something renders wrong, a `DebugView` can color the
+triangles with their normals, depth, texture coordinates or IDs, or
+show how many times each pixel was drawn.
+*/
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use na::{Matrix3, Vector2, Vector3, Vector4};
+use sdl2::pixels::Color;
+
+use crate::antialiasing::{self, Antialiasing};
+use crate::camera::Camera;
+use crate::culling::{CullMode, FrontFace};
+use crate::framebuffer::Framebuffer;
+use crate::frustum::Frustum;
+use crate::lighting::{self, Light, Shading};
+use crate::material::{BlendMode, Material};
+use crate::parallel;
+use crate::raster::{self, Fragment};
+use crate::polygons::Tri;
+use crate::scene::Scene;
+use crate::shadow::{self, ShadowMap, SHADOW_MAP_SIZE};
+use crate::simd::Vertices;
+use crate::texture::{self, Texture, TextureFilter};
+use crate::transparency::{Accumulation, Transparency};
+
+/// Color of the wireframe drawn over filled triangles
+const WIREFRAME_COLOR: Color = Color::RGB(24, 24, 24);
+/// Fraction of their distance the wireframe lines are pulled towards the
+/// camera, so they aren't hidden by the triangles they outline
+const WIREFRAME_DEPTH_OFFSET: f32 = 0.005;
+/// Size of the vertices in the points mode, in pixels
+const POINT_SIZE: usize = 3;
+/// Color of the normal vectors drawn from the triangles
+const NORMAL_COLOR: Color = Color::RGB(255, 220, 0);
+/// Length of the normal vectors, as a fraction of the radius of their mesh
+const NORMAL_LENGTH: f32 = 0.1;
+/// Times a pixel has to be drawn to be the hottest in the overdraw heatmap
+const OVERDRAW_MAX: u32 = 8;
+/// Triangles projected together on a thread
+const CHUNK_SIZE: usize = 1024;
+/// Width and height of the tiles filled in parallel, in pixels
+const TILE_SIZE: usize = 64;
+
+/// How the triangles are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    Filled,
+    /// Only the edges of the triangles, hidden ones included
+    Wireframe,
+    /// Filled triangles with their visible edges on top
+    FilledWireframe,
+    /// Only the vertices
+    Points,
+    /// Filled with SDL_gfx on the window canvas instead of the framebuffer,
+    /// to compare with a reference rasterizer
+    Gfx
+}
+
+impl RenderMode {
+    /// The mode after this one, going back to the first after the last
+    pub fn next(self) -> Self {
+        match self {
+            RenderMode::Filled => RenderMode::Wireframe,
+            RenderMode::Wireframe => RenderMode::FilledWireframe,
+            RenderMode::FilledWireframe => RenderMode::Points,
+            RenderMode::Points => RenderMode::Gfx,
+            RenderMode::Gfx => RenderMode::Filled
+        }
+    }
+}
+
+/// What the triangles are colored with, instead of their lit material
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    None,
+    /// Normal of each triangle in world space, x, y and z as red, green and blue
+    FaceNormals,
+    /// Normals of the vertices, interpolated across the triangles
+    VertexNormals,
+    /// Distance from the camera, from white up close to dark gray far away
+    Depth,
+    /// Texture coordinates, u as red and v as green
+    Uvs,
+    /// A random color for each triangle
+    TriangleIds,
+    /// Times each pixel was drawn, from blue for once to red for `OVERDRAW_MAX`
+    Overdraw
+}
+
+impl DebugView {
+    /// The view after this one, going back to the first after the last
+    pub fn next(self) -> Self {
+        match self {
+            DebugView::None => DebugView::FaceNormals,
+            DebugView::FaceNormals => DebugView::VertexNormals,
+            DebugView::VertexNormals => DebugView::Depth,
+            DebugView::Depth => DebugView::Uvs,
+            DebugView::Uvs => DebugView::TriangleIds,
+            DebugView::TriangleIds => DebugView::Overdraw,
+            DebugView::Overdraw => DebugView::None
+        }
+    }
+}
+
+/// Options of the pipeline
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    pub mode: RenderMode,
+    pub shading: Shading,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    /// Test each triangle against the frustum, not only whole instances
+    pub triangle_culling: bool,
+    pub debug: DebugView,
+    /// Draw the normal of each triangle as a line from its middle
+    pub show_normals: bool,
+    /// Width of the wireframe and normal lines, in pixels
+    pub line_width: f32,
+    pub antialiasing: Antialiasing,
+    pub transparency: Transparency,
+    pub texture_filter: TextureFilter,
+    /// Light the filled triangles per pixel, with the shadows of the lights
+    pub shadows: bool,
+    /// Bend the normals of the materials with a normal map, lighting them per pixel
+    pub normal_mapping: bool,
+    /// Threads projecting and filling the triangles, 1 to do it all on the calling thread
+    pub threads: usize
+}
+
+/// What happened to the objects and triangles of a frame, and how long it took
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub objects: usize,
+    /// Objects out of view
+    pub objects_culled: usize,
+    /// Triangles of every object in the scene
+    pub triangles: usize,
+    /// Triangles out of view, on their own or with their object
+    pub triangles_culled: usize,
+    /// Triangles skipped by face culling
+    pub triangles_face_culled: usize,
+    /// Triangles cut by the near plane
+    pub triangles_clipped: usize,
+    /// Triangles rasterized, after clipping
+    pub triangles_drawn: usize,
+    /// Triangles drawn with a transparent material
+    pub triangles_transparent: usize,
+    /// Time spent transforming, culling, clipping and lighting
+    pub geometry_time: Duration,
+    pub raster_time: Duration
+}
+
+/// Triangles of a frame in screen space, ready to rasterize
+#[derive(Default)]
+pub struct Projected {
+    pub opaque: Vec<Tri>,
+    /// Triangles with a transparent material, with how they blend,
+    /// from the furthest to the closest
+    pub transparent: Vec<(Tri, BlendMode)>,
+    /// Lights to light the triangles with per pixel, instead of the
+    /// colors they got when they were projected
+    pub lighting: Option<PixelLighting>,
+    /// Textures of the scene, which the triangles refer to by index
+    pub textures: Vec<Arc<Texture>>
+}
+
+/// Lights of a frame, lighting the triangles per pixel so they can cast
+/// shadows on each other
+pub struct PixelLighting {
+    pub lights: Vec<Light>,
+    /// Shadow map of each light, `None` for the lights not casting
+    /// shadows, or all of them without shadows
+    pub shadow_maps: Vec<Option<ShadowMap>>,
+    /// Position of the camera, the side of the triangles facing it is lit
+    pub eye: Vector3<f32>
+}
+
+impl PixelLighting {
+    /// Normal of the triangle in world space, on the side facing the camera
+    fn facing_normal(&self, tri: &Tri) -> Vector3<f32> {
+        let normal = (tri.world[1] - tri.world[0]).cross(&(tri.world[2] - tri.world[0]))
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::zeros);
+        if normal.dot(&(self.eye - tri.world[0])) < 0.0 { -normal } else { normal }
+    }
+
+    /// Normal of the triangle at the fragment, interpolated from its
+    /// vertices, on the side facing the camera
+    fn interpolated_normal(&self, tri: &Tri, facing_normal: &Vector3<f32>, fragment: &Fragment) -> Vector3<f32> {
+        match fragment.interpolate(&tri.n).try_normalize(f32::EPSILON) {
+            Some(normal) if normal.dot(facing_normal) < 0.0 => -normal,
+            Some(normal) => normal,
+            None => *facing_normal
+        }
+    }
+
+    /// Normal of the triangle at the fragment, bent by its normal map
+    ///
+    /// The map gives the normal in the space of the tangent, bitangent
+    /// and normal of the surface, interpolated from the vertices, with
+    /// green along the bitangent, each axis from -1 at 0 to 1 at 255.
+    fn mapped_normal(
+        &self,
+        tri: &Tri,
+        facing_normal: &Vector3<f32>,
+        fragment: &Fragment,
+        map: &Texture,
+        filter: TextureFilter
+    ) -> Vector3<f32> {
+        let normal = match fragment.interpolate(&tri.n).try_normalize(f32::EPSILON) {
+            Some(normal) => normal,
+            None => return *facing_normal
+        };
+        let tangent = fragment.interpolate(&tri.tangent);
+        let handedness = tangent.w.signum();
+        let tangent = match (tangent.xyz() - normal * normal.dot(&tangent.xyz())).try_normalize(f32::EPSILON) {
+            Some(tangent) => tangent,
+            None => return *facing_normal
+        };
+        let bitangent = normal.cross(&tangent) * handedness;
+
+        let (dx, dy) = fragment.derivatives(&tri.uv);
+        let texel = map.sample(fragment.interpolate(&tri.uv), dx, dy, filter);
+        let axis = |c: u8| c as f32 / 255.0 * 2.0 - 1.0;
+        let mapped = (tangent * axis(texel.r) + bitangent * axis(texel.g) + normal * axis(texel.b))
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(normal);
+        // Seen from behind, the back of the surface is lit
+        if normal.dot(facing_normal) < 0.0 { -mapped } else { mapped }
+    }
+
+    /// Color of the triangle lit at the point of the fragment
+    fn shade(&self, tri: &Tri, normal: &Vector3<f32>, fragment: &Fragment) -> Color {
+        let point = fragment.interpolate(&tri.world);
+        lighting::shade(normal, &point, &self.lights, tri.c.unwrap_or(Color::GREEN), |light| {
+            self.shadow_maps[light].as_ref().map_or(1.0, |map| map.visibility(&point, normal))
+        })
+    }
+}
+
+impl Projected {
+    /// Every triangle, the opaque ones first
+    pub fn tris(&self) -> impl Iterator<Item = &Tri> {
+        self.opaque.iter().chain(self.transparent.iter().map(|(tri, _)| tri))
+    }
+}
+
+/// Renders the scene as seen from the camera into the framebuffer
+///
+/// The framebuffer is not cleared, so the caller can choose the background.
+/// In the `Gfx` mode nothing is drawn, the caller draws the triangles
+/// from `project_scene` on its canvas.
+pub fn render_scene(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    framebuffer: &mut Framebuffer
+) -> RenderStats {
+    // Supersampling renders the frame bigger, lines as wide for their size
+    if let Antialiasing::Ssaa(samples) = settings.antialiasing {
+        let grid = antialiasing::ssaa_grid(samples);
+        let mut supersampled = antialiasing::supersample(framebuffer, grid);
+        let supersampled_settings = RenderSettings {
+            antialiasing: Antialiasing::None,
+            line_width: settings.line_width * (samples as f32).sqrt(),
+            ..*settings
+        };
+        let mut stats = render_scene(scene, camera, &supersampled_settings, &mut supersampled);
+
+        let start = Instant::now();
+        antialiasing::downsample(&supersampled, framebuffer, grid);
+        stats.raster_time += start.elapsed();
+        return stats;
+    }
+    framebuffer.set_samples(settings.antialiasing.msaa_samples());
+
+    let (mut projected, mut stats) = project_scene(scene, camera, settings, framebuffer.width, framebuffer.height);
+
+    let start = Instant::now();
+    if projected.tris().any(|tri| lit_per_pixel(tri, settings)) {
+        let shadow_maps = if settings.shadows {
+            shadow::render_shadow_maps(scene, SHADOW_MAP_SIZE, settings.threads)
+        } else {
+            scene.lights.iter().map(|_| None).collect()
+        };
+        projected.lighting = Some(PixelLighting {
+            lights: scene.lights.clone(),
+            shadow_maps,
+            eye: camera.position
+        });
+    }
+    rasterize(framebuffer, &projected, settings);
+    match settings.debug {
+        DebugView::Depth => framebuffer.pixels = framebuffer.depth_image(camera.near, camera.far),
+        DebugView::Overdraw => {
+            for i in 0..framebuffer.overdraw.len() {
+                let color = heat(framebuffer.overdraw[i]);
+                framebuffer.pixels[i * 3..i * 3 + 3].copy_from_slice(&[color.r, color.g, color.b]);
+            }
+        },
+        _ => {}
+    }
+    if settings.show_normals && settings.mode != RenderMode::Gfx {
+        draw_normals(framebuffer, scene, camera, settings.line_width);
+    }
+    stats.raster_time = start.elapsed();
+    stats
+}
+
+/// Draws triangles in screen space into the framebuffer, in the way the
+/// render mode and debug view ask for
+///
+/// The transparent triangles are blended after the opaque ones are
+/// filled, and with multisampling, all of them are resolved before lines
+/// and points are drawn over them. With more than one thread the
+/// triangles are filled in tiles in parallel, the lines and points are
+/// drawn on one thread.
+pub fn rasterize(framebuffer: &mut Framebuffer, projected: &Projected, settings: &RenderSettings) {
+    if matches!(settings.mode, RenderMode::Filled | RenderMode::FilledWireframe) {
+        if settings.threads > 1 {
+            fill_tiles(framebuffer, projected, settings);
+        } else {
+            let opaque: Vec<&Tri> = projected.opaque.iter().collect();
+            let transparent: Vec<&(Tri, BlendMode)> = projected.transparent.iter().collect();
+            fill_layers(framebuffer, &opaque, &transparent, projected, settings);
+        }
+    }
+
+    match settings.mode {
+        RenderMode::Wireframe => {
+            for tri in projected.tris() {
+                draw_edges(framebuffer, tri, tri.c.unwrap_or(Color::GREEN), settings.line_width, None);
+            }
+        },
+        RenderMode::FilledWireframe => {
+            for tri in projected.tris() {
+                draw_edges(framebuffer, tri, WIREFRAME_COLOR, settings.line_width, Some(WIREFRAME_DEPTH_OFFSET));
+            }
+        },
+        RenderMode::Points => {
+            for tri in projected.tris() {
+                for p in tri.p.iter() {
+                    raster::draw_point(framebuffer, p, tri.c.unwrap_or(Color::GREEN), POINT_SIZE);
+                }
+            }
+        },
+        RenderMode::Filled | RenderMode::Gfx => {}
+    }
+}
+
+/// Fills the opaque triangles, blends the transparent ones over them in
+/// order, and resolves the samples
+///
+/// The triangles are some of the projected ones, which give the lights
+/// and textures they are shaded with. With weighted blended
+/// transparency the alpha blended triangles are accumulated after
+/// resolving instead, once per pixel, and composited.
+fn fill_layers(
+    framebuffer: &mut Framebuffer,
+    opaque: &[&Tri],
+    transparent: &[&(Tri, BlendMode)],
+    projected: &Projected,
+    settings: &RenderSettings
+) {
+    for tri in opaque.iter() {
+        raster::fill_triangle_with(framebuffer, tri, shader(tri, projected, settings));
+    }
+    let weighted = |mode: BlendMode| settings.transparency == Transparency::Weighted && mode == BlendMode::Alpha;
+    for (tri, mode) in transparent.iter().filter(|(_, mode)| !weighted(*mode)) {
+        raster::blend_triangle_with(framebuffer, tri, *mode, shader(tri, projected, settings));
+    }
+    framebuffer.resolve();
+
+    if transparent.iter().any(|(_, mode)| weighted(*mode)) {
+        let mut accumulation = Accumulation::new(framebuffer.width, framebuffer.height);
+        for (tri, _) in transparent.iter().filter(|(_, mode)| weighted(*mode)) {
+            raster::accumulate_triangle_with(framebuffer, &mut accumulation, tri, shader(tri, projected, settings));
+        }
+        accumulation.composite(framebuffer);
+    }
+}
+
+/// Colors the pixels of the triangle from its fragments: with its own
+/// color, lit per pixel with its normal map, times its texture, or with
+/// the vertex attributes the debug view shows
+fn shader<'a>(
+    tri: &'a Tri,
+    projected: &'a Projected,
+    settings: &RenderSettings
+) -> impl Fn(Fragment) -> Color + 'a {
+    let (debug, filter) = (settings.debug, settings.texture_filter);
+    let smooth = settings.shading == Shading::Phong;
+    let lit = projected.lighting.as_ref()
+        .filter(|_| lit_per_pixel(tri, settings))
+        .map(|lighting| (lighting, lighting.facing_normal(tri)));
+    let texture = tri.texture.map(|index| &*projected.textures[index]);
+    let normal_map = tri.normal_map.map(|index| &*projected.textures[index]);
+    move |fragment| match debug {
+        DebugView::VertexNormals => normal_color(&fragment.interpolate(&tri.n)),
+        DebugView::Uvs => uv_color(&fragment.interpolate(&tri.uv)),
+        _ => {
+            let color = match &lit {
+                Some((lighting, normal)) => {
+                    let normal = match normal_map {
+                        Some(map) => lighting.mapped_normal(tri, normal, &fragment, map, filter),
+                        None if smooth => lighting.interpolated_normal(tri, normal, &fragment),
+                        None => *normal
+                    };
+                    lighting.shade(tri, &normal, &fragment)
+                },
+                None => tri.c.unwrap_or(Color::GREEN)
+            };
+            match texture {
+                Some(texture) => {
+                    let (dx, dy) = fragment.derivatives(&tri.uv);
+                    texture::modulate(color, texture.sample(fragment.interpolate(&tri.uv), dx, dy, filter))
+                },
+                None => color
+            }
+        }
+    }
+}
+
+/// Whether the filled triangles can be lit per pixel, which they are to
+/// get shadows, smooth or bend their normals, instead of once when they
+/// are projected
+fn lights_per_pixel(settings: &RenderSettings) -> bool {
+    matches!(settings.shading, Shading::Flat | Shading::Phong) && settings.debug == DebugView::None
+        && matches!(settings.mode, RenderMode::Filled | RenderMode::FilledWireframe)
+}
+
+/// Whether the projected triangle is lit per pixel
+fn lit_per_pixel(tri: &Tri, settings: &RenderSettings) -> bool {
+    lights_per_pixel(settings)
+        && (settings.shadows || settings.shading == Shading::Phong || tri.normal_map.is_some())
+}
+
+/// Draws the three edges of the triangle
+fn draw_edges(framebuffer: &mut Framebuffer, tri: &Tri, color: Color, width: f32, depth_offset: Option<f32>) {
+    for i in 0..3 {
+        raster::draw_line(framebuffer, &tri.p[i], &tri.p[(i + 1) % 3], color, width, depth_offset);
+    }
+}
+
+/// Transforms, culls, clips and lights the triangles of the scene,
+/// returning the ones left in screen space, for a screen of the given size
+///
+/// The triangles of the instances in view are projected in chunks spread
+/// across the threads, and come back in the same order as with one thread.
+/// The transparent ones are sorted from the furthest to the closest, the
+/// ones at the same depth keeping their order.
+pub fn project_scene(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    width: usize,
+    height: usize
+) -> (Projected, RenderStats) {
+    let start = Instant::now();
+    let (width, height) = (width as f32, height as f32);
+    let default_material = Material::default();
+    let mut stats = RenderStats::default();
+
+    // View and projection matrices
+    let view_matrix = camera.view_matrix();
+    let proj_matrix = camera.projection_matrix(width / height);
+    let frustum = Frustum::from_matrix(&(proj_matrix * view_matrix));
+
+    // Chunks of the triangles of the instances in view
+    let instances = scene.instances();
+    let mut chunks = Vec::new();
+    for (instance_index, instance) in instances.iter().enumerate() {
+        let triangle_count = scene.meshes[instance.mesh].tris.len();
+
+        // Skip the whole instance if it is out of view
+        stats.objects += 1;
+        stats.triangles += triangle_count;
+        let bounds = scene.mesh_bounds[instance.mesh].transform(&instance.world_matrix);
+        if !frustum.intersects_sphere(&bounds) {
+            stats.objects_culled += 1;
+            stats.triangles_culled += triangle_count;
+            continue;
+        }
+        for first in (0..triangle_count).step_by(CHUNK_SIZE) {
+            chunks.push((instance_index, first..(first + CHUNK_SIZE).min(triangle_count)));
+        }
+    }
+
+    let projected_chunks = parallel::map(&chunks, settings.threads, |(instance_index, range)| {
+        let instance = &instances[*instance_index];
+        let world_matrix = instance.world_matrix;
+        let normal_matrix = instance.normal_matrix;
+        // Tangents follow the surface, a mirroring matrix flipping their bitangents
+        let tangent_matrix: Matrix3<f32> = world_matrix.fixed_slice::<3, 3>(0, 0).into();
+        let handedness = tangent_matrix.determinant().signum();
+        let material = instance.material.map_or(&default_material, |m| &scene.materials[m]);
+        let normal_map = material.normal_map.filter(|_| settings.normal_mapping && lights_per_pixel(settings));
+        let mut stats = RenderStats::default();
+        // Triangles ready to rasterize, in screen space
+        let mut visible = Projected::default();
+
+        // Every vertex of the chunk to world and view space, 4 at a time
+        let tris = &scene.meshes[instance.mesh].tris[range.clone()];
+        let world = Vertices::from_tris(tris).transform(&world_matrix);
+        let view = world.transform(&view_matrix);
+        let vertices = |vertices: &Vertices, k: usize| [vertices.get(3 * k), vertices.get(3 * k + 1), vertices.get(3 * k + 2)];
+
+        for (k, triangle) in tris.iter().enumerate() {
+            let triangle_index = range.start + k;
+            // transform triangle
+            let mut transformed = *triangle;
+            transformed.p = vertices(&world, k);
+            transformed.world = transformed.p.map(|p| p.xyz());
+
+            // Vertex normals in world space, the face normal for models without them
+            let face_normal = transformed.normal();
+            for n in transformed.n.iter_mut() {
+                *n = if *n == Vector3::zeros() { face_normal } else { (normal_matrix * *n).normalize() };
+            }
+            if normal_map.is_some() {
+                for t in transformed.tangent.iter_mut() {
+                    let tangent = (tangent_matrix * t.xyz()).try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros);
+                    *t = Vector4::new(tangent.x, tangent.y, tangent.z, t.w * handedness);
+                }
+            }
+
+            // Skip triangles out of view
+            if settings.triangle_culling && !frustum.intersects_tri(&transformed) {
+                stats.triangles_culled += 1;
+                continue;
+            }
+
+            // Move triangle to camera
+            let viewed = Tri { p: vertices(&view, k), ..transformed };
+
+            // Cut off the parts behind the near plane, they can't be projected
+            let near_point = Vector3::new(0.0, 0.0, camera.near);
+            let clipped_tris = viewed.clip_against_plane(near_point, Vector3::new(0.0, 0.0, 1.0));
+            if clipped_tris.len() != 1 || clipped_tris[0].p != viewed.p {
+                stats.triangles_clipped += 1;
+            }
+            for clipped in clipped_tris {
+                // Project triangles from 3D to 2D
+                let mut projected = proj_matrix * clipped;
+                for p in projected.p.iter_mut() {
+                    *p = to_screen(*p, width, height);
+                }
+
+                // Face culling, by the winding of the triangle on screen
+                let front_facing = settings.front_face.is_front_facing(&projected);
+                if settings.cull_mode.culls(front_facing) || projected.signed_area() == 0.0 {
+                    stats.triangles_face_culled += 1;
+                    continue;
+                }
+
+                // Calculate normal, facing the side of the triangle being seen
+                let mut normal = face_normal;
+                if (settings.front_face == FrontFace::Clockwise) == front_facing {
+                    normal = -normal;
+                }
+
+                projected.normal_map = normal_map;
+
+                // Light and Color, the debug views interpolating vertex
+                // attributes color the edges and points with their average
+                projected.c = Some(match settings.debug {
+                    DebugView::FaceNormals => normal_color(&face_normal),
+                    DebugView::VertexNormals => normal_color(&(projected.n[0] + projected.n[1] + projected.n[2])),
+                    DebugView::Uvs => uv_color(&((projected.uv[0] + projected.uv[1] + projected.uv[2]) / 3.0)),
+                    DebugView::TriangleIds => id_color(*instance_index, triangle_index),
+                    _ => match settings.shading {
+                        Shading::Flat | Shading::Phong if lit_per_pixel(&projected, settings) => material.color,
+                        // Lines and points of the phong shading are lit flat
+                        Shading::Flat | Shading::Phong => {
+                            let midpoint = (projected.world[0] + projected.world[1] + projected.world[2]) / 3.0;
+                            lighting::shade(&normal, &midpoint, &scene.lights, material.color, |_| 1.0)
+                        },
+                        Shading::Unlit => material.color
+                    }
+                });
+
+                // Only the lit material is textured, not the debug colors
+                if settings.debug == DebugView::None {
+                    projected.texture = material.texture;
+                }
+
+                if material.is_transparent() {
+                    if let Some(color) = &mut projected.c {
+                        color.a = (material.opacity * 255.0).round() as u8;
+                    }
+                    visible.transparent.push((projected, material.blend));
+                } else {
+                    visible.opaque.push(projected);
+                }
+            }
+        }
+        (visible, stats)
+    });
+
+    let mut visible = Projected { textures: scene.textures.clone(), ..Projected::default() };
+    for (tris, chunk_stats) in projected_chunks {
+        visible.opaque.extend(tris.opaque);
+        visible.transparent.extend(tris.transparent);
+        stats.triangles_culled += chunk_stats.triangles_culled;
+        stats.triangles_face_culled += chunk_stats.triangles_face_culled;
+        stats.triangles_clipped += chunk_stats.triangles_clipped;
+    }
+    let depth = |tri: &Tri| tri.p[0].z + tri.p[1].z + tri.p[2].z;
+    visible.transparent.sort_by(|(a, _), (b, _)| depth(b).total_cmp(&depth(a)));
+    stats.triangles_transparent = visible.transparent.len();
+    stats.triangles_drawn = visible.opaque.len() + stats.triangles_transparent;
+    stats.geometry_time = start.elapsed();
+    (visible, stats)
+}
+
+/// Fills the triangles tile by tile, spreading the tiles across threads
+///
+/// Each tile gets the triangles overlapping it in the order they were
+/// given, so every pixel ends up exactly as when filled on one thread.
+fn fill_tiles(framebuffer: &mut Framebuffer, projected: &Projected, settings: &RenderSettings) {
+    let columns = framebuffer.width.div_ceil(TILE_SIZE);
+    let rows = framebuffer.height.div_ceil(TILE_SIZE);
+
+    // Bin the triangles in the tiles their bounding box overlaps
+    let bin = |tris: &mut dyn Iterator<Item = &Tri>| {
+        let mut bins = vec![Vec::new(); columns * rows];
+        for (index, tri) in tris.enumerate() {
+            let (min, max) = raster::pixel_bounds(tri, framebuffer.width, framebuffer.height);
+            if min.0 >= max.0 || min.1 >= max.1 {
+                continue;
+            }
+            for row in min.1 / TILE_SIZE..=(max.1 - 1) / TILE_SIZE {
+                for column in min.0 / TILE_SIZE..=(max.0 - 1) / TILE_SIZE {
+                    bins[row * columns + column].push(index);
+                }
+            }
+        }
+        bins
+    };
+    let opaque_bins = bin(&mut projected.opaque.iter());
+    let transparent_bins = bin(&mut projected.transparent.iter().map(|(tri, _)| tri));
+
+    let tiles: Vec<usize> = (0..columns * rows)
+        .filter(|&tile| !opaque_bins[tile].is_empty() || !transparent_bins[tile].is_empty())
+        .collect();
+    let filled = parallel::map(&tiles, settings.threads, |&tile| {
+        let (x, y) = (tile % columns * TILE_SIZE, tile / columns * TILE_SIZE);
+        let mut framebuffer_tile = framebuffer.tile(
+            x, y, TILE_SIZE.min(framebuffer.width - x), TILE_SIZE.min(framebuffer.height - y)
+        );
+        let opaque: Vec<&Tri> = opaque_bins[tile].iter().map(|&index| &projected.opaque[index]).collect();
+        let transparent: Vec<&(Tri, BlendMode)> = transparent_bins[tile].iter()
+            .map(|&index| &projected.transparent[index])
+            .collect();
+        fill_layers(&mut framebuffer_tile, &opaque, &transparent, projected, settings);
+        framebuffer_tile
+    });
+    for tile in filled.iter() {
+        framebuffer.put_tile(tile);
+    }
+}
+
+/// Moves a projected point to the screen, keeping 1/w in w to
+/// interpolate the vertex attributes with the perspective
+pub fn to_screen(p: Vector4<f32>, width: f32, height: f32) -> Vector4<f32> {
+    let w = p.w;
+    let normalized = p / w;
+    // Scale into view, the view space x points left and
+    // the screen y points down, so both axes are flipped
+    Vector4::new(
+        (1.0 - normalized.x) * 0.5 * width,
+        (1.0 - normalized.y) * 0.5 * height,
+        normalized.z,
+        1.0 / w
+    )
+}
+
+/// Draws the normal of every triangle in view as a line from its middle,
+/// hidden by the triangles in front of it
+fn draw_normals(framebuffer: &mut Framebuffer, scene: &Scene, camera: &Camera, line_width: f32) {
+    let (width, height) = (framebuffer.width as f32, framebuffer.height as f32);
+    let view_matrix = camera.view_matrix();
+    let proj_matrix = camera.projection_matrix(width / height);
+    let frustum = Frustum::from_matrix(&(proj_matrix * view_matrix));
+
+    for instance in scene.instances() {
+        let bounds = scene.mesh_bounds[instance.mesh].transform(&instance.world_matrix);
+        if !frustum.intersects_sphere(&bounds) {
+            continue;
+        }
+        for triangle in scene.meshes[instance.mesh].tris.iter() {
+            let transformed = instance.world_matrix * *triangle;
+            let start = transformed.midpoint();
+            let end = start + transformed.normal() * bounds.radius * NORMAL_LENGTH;
+
+            // Into view space, cutting off what is behind the near plane
+            let view = |p: Vector3<f32>| view_matrix * Vector4::new(p.x, p.y, p.z, 1.0);
+            let (mut a, mut b) = (view(start), view(end));
+            if a.z < camera.near && b.z < camera.near {
+                continue;
+            }
+            if a.z < camera.near || b.z < camera.near {
+                let t = (camera.near - a.z) / (b.z - a.z);
+                let cut = a + (b - a) * t;
+                if a.z < camera.near { a = cut } else { b = cut }
+            }
+            let a = to_screen(proj_matrix * a, width, height);
+            let b = to_screen(proj_matrix * b, width, height);
+            raster::draw_line(framebuffer, &a, &b, NORMAL_COLOR, line_width, Some(WIREFRAME_DEPTH_OFFSET));
+        }
+    }
+}
+
+/// A normal as a color, each axis going from 0 at -1 to 255 at 1
+fn normal_color(normal: &Vector3<f32>) -> Color {
+    let n = normal.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros);
+    let channel = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+    Color::RGB(channel(n.x), channel(n.y), channel(n.z))
+}
+
+/// Texture coordinates as a color, repeating outside of [0, 1]
+fn uv_color(uv: &Vector2<f32>) -> Color {
+    let channel = |v: f32| (v.rem_euclid(1.0) * 255.0) as u8;
+    Color::RGB(channel(uv.x), channel(uv.y), 0)
+}
+
+/// A color that looks random, but is always the same for a triangle
+fn id_color(instance: usize, triangle: usize) -> Color {
+    // Mix the bits of both indices, so neighbours get unrelated colors
+    let mut h = (instance as u32).wrapping_mul(0x9e37_79b9) ^ triangle as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+    Color::RGB(h as u8, (h >> 8) as u8, (h >> 16) as u8)
+}
+
+/// Color of a pixel drawn some number of times in the overdraw heatmap,
+/// black for none, then from blue through green to red
+fn heat(count: u32) -> Color {
+    if count == 0 {
+        return Color::BLACK;
+    }
+    let t = (count - 1).min(OVERDRAW_MAX - 1) as f32 / (OVERDRAW_MAX - 1) as f32;
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color::RGB(channel(2.0 * t - 1.0), channel(1.0 - (2.0 * t - 1.0).abs()), channel(1.0 - 2.0 * t))
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_colors() {
+        assert_eq!(normal_color(&Vector3::new(0.0, 2.0, 0.0)), Color::RGB(128, 255, 128));
+        assert_eq!(normal_color(&Vector3::new(-1.0, 0.0, 0.0)), Color::RGB(0, 128, 128));
+        assert_eq!(uv_color(&Vector2::new(0.5, 1.25)), Color::RGB(127, 63, 0));
+        assert_eq!(id_color(3, 14), id_color(3, 14));
+        assert_ne!(id_color(3, 14), id_color(3, 15));
+
+        assert_eq!(heat(0), Color::BLACK);
+        assert_eq!(heat(1), Color::RGB(0, 0, 255));
+        assert_eq!(heat(OVERDRAW_MAX), Color::RGB(255, 0, 0));
+        assert_eq!(heat(OVERDRAW_MAX * 2), heat(OVERDRAW_MAX));
+    }
+
+    #[test]
+    fn test_tiles() {
+        // Overlapping triangles all over the screen and past its edges,
+        // at random depths, from a fixed seed
+        let mut seed = 12345u32;
+        let mut random = |scale: f32| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * scale
+        };
+        let tris: Vec<Tri> = (0..300).map(|_| {
+            let mut tri = Tri::from([0.0; 9]);
+            for p in tri.p.iter_mut() {
+                *p = Vector4::new(random(260.0) - 30.0, random(200.0) - 30.0, random(1.0), 0.5 + random(1.0));
+            }
+            tri.uv = [Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0), Vector2::new(0.0, 1.0)];
+            tri.c = Some(Color::RGB(random(255.0) as u8, random(255.0) as u8, random(255.0) as u8));
+            tri.texture = (random(1.0) < 0.5).then_some(0);
+            tri.n = [Vector3::new(random(1.0), random(1.0), 1.0); 3];
+            tri.tangent = [Vector4::new(1.0, 0.0, 0.0, 1.0); 3];
+            tri.world = tri.p.map(|p| p.xyz());
+            tri.normal_map = (random(1.0) < 0.5).then_some(0);
+            tri
+        }).collect();
+        // A third of them transparent, blending in every way
+        let modes = [BlendMode::Alpha, BlendMode::Additive, BlendMode::Multiply];
+        // Textured with a gradient, sampled with the derivatives of the quads
+        let pixels: Vec<u8> = (0..32 * 32).flat_map(|i| [(i % 32 * 8) as u8, (i / 32 * 8) as u8, 255]).collect();
+        let mut projected = Projected { textures: vec![Arc::new(Texture::new(32, 32, &pixels))], ..Projected::default() };
+        // Half of them lit per pixel, the gradient bending their normals
+        projected.lighting = Some(PixelLighting {
+            lights: vec![Light::directional(Vector3::new(-1.0, -1.0, -1.0))],
+            shadow_maps: vec![None],
+            eye: Vector3::new(100.0, 75.0, -10.0)
+        });
+        for (i, mut tri) in tris.into_iter().enumerate() {
+            if i % 3 == 0 {
+                tri.c.as_mut().unwrap().a = 128;
+                projected.transparent.push((tri, modes[i / 3 % 3]));
+            } else {
+                projected.opaque.push(tri);
+            }
+        }
+
+        let methods = [Transparency::Sorted, Transparency::Weighted];
+        let views = [(DebugView::Uvs, TextureFilter::Nearest), (DebugView::None, TextureFilter::Anisotropic(4))];
+        for ((antialiasing, transparency), (debug, texture_filter)) in [Antialiasing::None, Antialiasing::Msaa(4)]
+            .into_iter()
+            .flat_map(|antialiasing| methods.map(|transparency| (antialiasing, transparency)))
+            .flat_map(|modes| views.map(|view| (modes, view)))
+        {
+            let settings = RenderSettings {
+                mode: RenderMode::Filled,
+                shading: Shading::Flat,
+                cull_mode: CullMode::None,
+                front_face: FrontFace::CounterClockwise,
+                triangle_culling: true,
+                debug,
+                show_normals: false,
+                line_width: 1.0,
+                antialiasing,
+                transparency,
+                texture_filter,
+                shadows: false,
+                normal_mapping: true,
+                threads: 1
+            };
+            let render = |threads: usize| {
+                let mut framebuffer = Framebuffer::new(200, 150);
+                framebuffer.set_samples(antialiasing.msaa_samples());
+                framebuffer.clear(Color::BLACK);
+                rasterize(&mut framebuffer, &projected, &RenderSettings { threads, ..settings });
+                framebuffer
+            };
+            // The same frame, bit for bit, on one thread or in tiles on several
+            let (single, tiled) = (render(1), render(4));
+            assert!(single.pixels == tiled.pixels);
+            assert!(single.depth == tiled.depth);
+            assert!(single.overdraw == tiled.overdraw);
+        }
+    }
+
+    #[test]
+    fn test_normal_map() {
+        // A triangle facing +z, u along +x and v along +y, or -y mirrored
+        let mut tri = Tri::from([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        tri.world = tri.p.map(|p| p.xyz());
+        tri.n = [Vector3::z(); 3];
+        tri.tangent = [Vector4::new(1.0, 0.0, 0.0, 1.0); 3];
+        let lighting = PixelLighting { lights: Vec::new(), shadow_maps: Vec::new(), eye: Vector3::new(0.2, 0.2, 5.0) };
+        let fragment = Fragment { weights: [1.0 / 3.0; 3], dx: [0.0; 3], dy: [0.0; 3] };
+        let facing_normal = lighting.facing_normal(&tri);
+        let mapped = |tri: &Tri, color: [u8; 3]| {
+            let map = Texture::new(1, 1, &color);
+            lighting.mapped_normal(tri, &facing_normal, &fragment, &map, TextureFilter::Nearest)
+        };
+
+        // Without a map, phong shading takes the normal of the vertices
+        assert_eq!(lighting.interpolated_normal(&tri, &facing_normal, &fragment), Vector3::z());
+
+        // Flat maps keep the normal, others tilt it along the tangent and bitangent
+        assert!((mapped(&tri, [128, 128, 255]) - Vector3::z()).magnitude() < 0.01);
+        let tilted = Vector3::new(1.0, 0.0, 1.0).normalize();
+        assert!((mapped(&tri, [218, 128, 218]) - tilted).magnitude() < 0.01);
+        let up = mapped(&tri, [128, 218, 218]);
+        assert!(up.y > 0.5 && up.x.abs() < 0.01);
+        tri.tangent = [Vector4::new(1.0, 0.0, 0.0, -1.0); 3];
+        let down = mapped(&tri, [128, 218, 218]);
+        assert!(down.y < -0.5 && down.x.abs() < 0.01);
+
+        // Seen from behind, the normal bends the same way on the back
+        let behind = PixelLighting { eye: Vector3::new(0.2, 0.2, -5.0), ..lighting };
+        let map = Texture::new(1, 1, &[218, 128, 218]);
+        let back = behind.mapped_normal(&tri, &behind.facing_normal(&tri), &fragment, &map, TextureFilter::Nearest);
+        assert!((back + tilted).magnitude() < 0.01);
+        assert_eq!(behind.interpolated_normal(&tri, &behind.facing_normal(&tri), &fragment), -Vector3::z());
+    }
+
+    #[test]
+    fn test_to_screen() {
+        // The center of the view, at half the distance, keeps 1/w
+        let p = to_screen(Vector4::new(0.0, 0.0, 1.0, 2.0), 800.0, 600.0);
+        assert_eq!(p, Vector4::new(400.0, 300.0, 0.5, 0.5));
+    }
+}
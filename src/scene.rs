@@ -0,0 +1,210 @@
+/*!
+# scene.rs
+
+A scene graph: a tree of nodes, each with its own `Transform`
+relative to its parent and optionally a mesh to draw.
+
+Meshes are stored once in the scene and referenced by index,
+so the same model can be drawn by many nodes. So are the materials,
+and the textures of the materials.
+*/
+
+use std::sync::Arc;
+
+use na::{Matrix3, Matrix4, Vector3};
+
+use crate::bounds::BoundingSphere;
+use crate::lighting::Light;
+use crate::material::Material;
+use crate::polygons::Mesh;
+use crate::texture::Texture;
+use crate::transform::Transform;
+
+/// A node of the scene graph
+pub struct Node {
+    pub name: String,
+    /// Transform relative to the parent node
+    pub transform: Transform,
+    /// Index of the mesh drawn by this node, if any
+    pub mesh: Option<usize>,
+    /// Index of the material of the mesh, if any
+    pub material: Option<usize>,
+    /// Angular velocity in radians per second around the x, y and z axes
+    pub spin: Vector3<f32>,
+    /// Indices of the child nodes
+    pub children: Vec<usize>
+}
+
+/// A mesh placed in the world
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    #[allow(dead_code)]
+    pub node: usize,
+    pub mesh: usize,
+    pub material: Option<usize>,
+    pub world_matrix: Matrix4<f32>,
+    /// Moves the normals of the mesh to world space, see `Transform::normal_matrix`
+    pub normal_matrix: Matrix3<f32>
+}
+
+/// Meshes and the hierarchy of nodes that place them in the world
+#[derive(Default)]
+pub struct Scene {
+    pub meshes: Vec<Mesh>,
+    /// Bounding sphere of each mesh, in its own local space
+    pub mesh_bounds: Vec<BoundingSphere>,
+    pub nodes: Vec<Node>,
+    /// Nodes without a parent
+    pub roots: Vec<usize>,
+    pub materials: Vec<Material>,
+    /// Shared with the triangles of the frames rendered from the scene
+    pub textures: Vec<Arc<Texture>>,
+    pub lights: Vec<Light>
+}
+
+impl Node {
+    /// Creates a node without children
+    pub fn new(name: &str, transform: Transform, mesh: Option<usize>) -> Self {
+        Self {
+            name: name.to_string(),
+            transform,
+            mesh,
+            material: None,
+            spin: Vector3::zeros(),
+            children: Vec::new()
+        }
+    }
+}
+
+impl Scene {
+    /// Creates an empty scene
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a mesh to the scene, returning its index
+    pub fn add_mesh(&mut self, mesh: Mesh) -> usize {
+        self.mesh_bounds.push(mesh.bounding_sphere());
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    /// Adds a node under the given parent, or as a root, returning its index
+    pub fn add_node(&mut self, node: Node, parent: Option<usize>) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(node);
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(index),
+            None => self.roots.push(index)
+        }
+        index
+    }
+
+    /// Adds a material to the scene, returning its index
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    /// Adds a texture to the scene, returning its index
+    pub fn add_texture(&mut self, texture: Texture) -> usize {
+        self.textures.push(Arc::new(texture));
+        self.textures.len() - 1
+    }
+
+    /// Advances the animation of the nodes by the given seconds
+    pub fn update(&mut self, dt: f32) {
+        for node in self.nodes.iter_mut().filter(|node| node.spin != Vector3::zeros()) {
+            let spin = node.spin * dt;
+            node.transform.rotation = Transform::euler_rotation(spin.x, spin.y, spin.z) * node.transform.rotation;
+        }
+    }
+
+    /// Finds the first node with the given name
+    #[allow(dead_code)]
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    /// World matrix of every node, with the matrix moving its normals to
+    /// world space, indexed like `nodes`
+    ///
+    /// Each matrix is the product of the transforms from the root down to
+    /// the node, and so is each normal matrix, the inverse transpose of a
+    /// product being the product of the inverse transposes.
+    pub fn world_matrices(&self) -> Vec<(Matrix4<f32>, Matrix3<f32>)> {
+        let identity = (Matrix4::identity(), Matrix3::identity());
+        let mut matrices = vec![identity; self.nodes.len()];
+        let mut stack: Vec<_> = self.roots.iter().map(|&root| (root, identity)).collect();
+
+        while let Some((index, (parent_matrix, parent_normal_matrix))) = stack.pop() {
+            let node = &self.nodes[index];
+            matrices[index] = (
+                parent_matrix * node.transform.matrix(),
+                parent_normal_matrix * node.transform.normal_matrix()
+            );
+            for &child in node.children.iter() {
+                stack.push((child, matrices[index]));
+            }
+        }
+        matrices
+    }
+
+    /// Every node with a mesh, with its world matrix
+    pub fn instances(&self) -> Vec<Instance> {
+        let matrices = self.world_matrices();
+        self.nodes.iter().enumerate()
+            .filter_map(|(index, node)| node.mesh.map(|mesh| Instance {
+                node: index,
+                mesh,
+                material: node.material,
+                world_matrix: matrices[index].0,
+                normal_matrix: matrices[index].1
+            }))
+            .collect()
+    }
+
+    /// Sphere enclosing every instance in the world
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        self.instances().iter()
+            .map(|instance| self.mesh_bounds[instance.mesh].transform(&instance.world_matrix))
+            .reduce(|a, b| a.merge(&b))
+            .unwrap_or(BoundingSphere::new(Vector3::zeros(), 0.0))
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polygons::Tri;
+
+    #[test]
+    fn test_hierarchy() {
+        let mut scene = Scene::new();
+        let mesh = scene.add_mesh(Mesh::new(vec![Tri::from([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0])]));
+
+        let parent = scene.add_node(Node::new(
+            "parent",
+            Transform { scale: Vector3::new(1.0, 2.0, 1.0), ..Transform::from_translation(Vector3::new(0.0, 0.0, -5.0)) },
+            None
+        ), None);
+        let child = scene.add_node(Node::new(
+            "child",
+            Transform::from_translation(Vector3::new(1.0, 0.0, 0.0)),
+            Some(mesh)
+        ), Some(parent));
+
+        let matrices = scene.world_matrices();
+        assert_eq!(matrices[child].0.column(3).xyz(), Vector3::new(1.0, 0.0, -5.0));
+        // Normals are moved by the inverse transpose of the whole chain
+        let linear: Matrix3<f32> = matrices[child].0.fixed_slice::<3, 3>(0, 0).into();
+        assert!((matrices[child].1 - linear.try_inverse().unwrap().transpose()).abs().max() < 1e-6);
+
+        let instances = scene.instances();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].node, child);
+        assert_eq!(scene.find("child"), Some(child));
+    }
+}
@@ -0,0 +1,749 @@
+/*!
+# scene_file.rs
+
+Loading of scene description files.
+
+A scene file is a TOML document describing the camera, lights,
+materials, meshes and the objects placing them in the world:
+
+```toml
+background = [15, 17, 17]
+
+[camera]
+fov = 60.0
+
+[[lights]]
+direction = [-1.0, -1.0, -1.0]
+
+[[materials]]
+name = "porcelain"
+color = [230, 230, 220]
+
+[[meshes]]
+name = "teapot"
+path = "teapot-trian.obj"
+center = true
+
+[[objects]]
+name = "teapot"
+mesh = "teapot"
+material = "porcelain"
+rotation = [-90.0, 0.0, 0.0]
+spin = [0.0, 30.0, 0.0]
+```
+
+Lights with a `position` are spot lights, lighting a cone `angle`
+degrees wide around their direction, and any light can stop casting
+shadows with `shadows = false`.
+
+Paths are relative to the scene file. Angles are in degrees, and
+objects can only be parented to objects declared before them.
+Materials with an `opacity` below 1, or blending with what is behind
+them with `blend = "additive"` or `"multiply"`, are transparent.
+Materials can multiply their color with an image, declared like meshes:
+
+```toml
+[[textures]]
+name = "checker"
+path = "checker.png"
+
+[[materials]]
+name = "floor"
+texture = "checker"
+normal_map = "tiles"
+```
+
+A `normal_map` bends the lighting of the surface by a normal map, in
+the space of the tangents of the mesh. Meshes with a .mtl library are
+split by its materials, and objects drawing them get a child node for
+each part, unless their own `material` overrides them all.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use na::Vector3;
+use serde::Deserialize;
+use sdl2::pixels::Color;
+
+use crate::camera::Camera;
+use crate::lighting::Light;
+use crate::material::{BlendMode, Material};
+use crate::mtl;
+use crate::polygons::Mesh;
+use crate::scene::{Node, Scene};
+use crate::texture::Texture;
+use crate::transform::Transform;
+
+/// A scene loaded from a file, with how to look at it
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: Camera,
+    /// Whether the camera has to be framed around the scene,
+    /// as the file didn't give it a position
+    pub frame_camera: bool,
+    pub background: Color
+}
+
+/// An error in a scene file, pointing to the entry causing it
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: String,
+    /// Entry of the file with the error, like `objects[2] "teapot"`
+    pub entry: Option<String>,
+    pub message: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default = "default_background")]
+    background: [u8; 3],
+    camera: Option<CameraDesc>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+    #[serde(default)]
+    textures: Vec<TextureDesc>,
+    #[serde(default)]
+    materials: Vec<MaterialDesc>,
+    #[serde(default)]
+    meshes: Vec<MeshDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    position: Option<[f32; 3]>,
+    #[serde(default = "default_direction")]
+    direction: [f32; 3],
+    #[serde(default = "default_fov")]
+    fov: f32,
+    near: Option<f32>,
+    far: Option<f32>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDesc {
+    direction: [f32; 3],
+    /// Makes it a spot light at this position
+    position: Option<[f32; 3]>,
+    /// Angle between the axis and the edge of the cone of a spot light, in degrees
+    angle: Option<f32>,
+    #[serde(default = "default_color")]
+    color: [u8; 3],
+    #[serde(default = "default_one")]
+    intensity: f32,
+    #[serde(default = "default_true")]
+    shadows: bool
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDesc {
+    name: String,
+    #[serde(default = "default_color")]
+    color: [u8; 3],
+    #[serde(default = "default_one")]
+    opacity: f32,
+    /// alpha, additive or multiply
+    blend: Option<String>,
+    texture: Option<String>,
+    normal_map: Option<String>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextureDesc {
+    name: String,
+    path: String
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshDesc {
+    name: String,
+    path: String,
+    /// Move the mesh so its bounding box is centered in the origin
+    #[serde(default)]
+    center: bool
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectDesc {
+    name: String,
+    mesh: Option<String>,
+    material: Option<String>,
+    parent: Option<String>,
+    #[serde(default)]
+    translation: [f32; 3],
+    /// Euler angles in degrees
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    scale: [f32; 3],
+    /// Degrees per second around each axis
+    #[serde(default)]
+    spin: [f32; 3]
+}
+
+fn default_background() -> [u8; 3] { [15, 17, 17] }
+fn default_direction() -> [f32; 3] { [0.0, 0.0, -1.0] }
+fn default_fov() -> f32 { 60.0 }
+fn default_color() -> [u8; 3] { [255, 255, 255] }
+fn default_one() -> f32 { 1.0 }
+fn default_true() -> bool { true }
+fn default_spot_angle() -> f32 { 30.0 }
+fn default_scale() -> [f32; 3] { [1.0, 1.0, 1.0] }
+
+/// The .mtl libraries and images added to a scene, by path, so models
+/// sharing them add them once
+#[derive(Default)]
+struct Libraries {
+    materials: HashMap<PathBuf, HashMap<String, usize>>,
+    textures: HashMap<PathBuf, Option<usize>>
+}
+
+impl Libraries {
+    /// Adds a model to the scene as a mesh for the triangles of each
+    /// material of its library, returning the name, mesh and material of
+    /// each part
+    ///
+    /// The library is looked for in the directory of the model.
+    fn add_model(&mut self, scene: &mut Scene, model: Mesh, dir: &Path) -> Vec<(String, usize, Option<usize>)> {
+        let materials = match &model.library {
+            Some(library) => self.load(scene, &dir.join(library)),
+            None => HashMap::new()
+        };
+        model.split_by_material().into_iter()
+            .map(|(name, mesh)| {
+                let material = name.as_ref().and_then(|name| materials.get(name)).copied();
+                (name.unwrap_or_else(|| "model".to_string()), scene.add_mesh(mesh), material)
+            })
+            .collect()
+    }
+
+    /// Adds the materials of a .mtl library to the scene, with their
+    /// images, returning their indices by name
+    ///
+    /// Models often come with broken libraries, so what can't be loaded is
+    /// only warned about.
+    fn load(&mut self, scene: &mut Scene, path: &Path) -> HashMap<String, usize> {
+        let path = resolve(path);
+        if let Some(materials) = self.materials.get(&path) {
+            return materials.clone();
+        }
+        let library = mtl::load(&path.to_string_lossy()).unwrap_or_else(|e| {
+            eprintln!("Warning: {}", e);
+            Vec::new()
+        });
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        let mut materials = HashMap::new();
+        for material in library {
+            let texture = material.texture.and_then(|image| self.load_texture(scene, &dir.join(image)));
+            let normal_map = material.normal_map.and_then(|image| self.load_texture(scene, &dir.join(image)));
+            let index = scene.add_material(Material {
+                opacity: material.opacity,
+                texture,
+                normal_map,
+                ..Material::new(&material.name, material.color)
+            });
+            materials.insert(material.name, index);
+        }
+        self.materials.insert(path, materials.clone());
+        materials
+    }
+
+    /// Adds the image at the given path to the scene, unless it already was
+    fn load_texture(&mut self, scene: &mut Scene, path: &Path) -> Option<usize> {
+        *self.textures.entry(resolve(path)).or_insert_with(|| {
+            match Texture::load(&path.to_string_lossy()) {
+                Ok(texture) => Some(scene.add_texture(texture)),
+                Err(e) => {
+                    eprintln!("Warning: {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+    }
+}
+
+/// The canonical form of a path, so the different ways to write it are
+/// one key
+fn resolve(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}: {}", self.path, entry, self.message),
+            None => write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl SceneFile {
+    /// A scene with a single model, lit from the front and framed by the camera
+    pub fn from_model(path: &str) -> Result<Self, SceneError> {
+        if !Path::new(path).is_file() {
+            return Err(SceneError::new(path, None, "can't find the file"));
+        }
+        let mut model = Mesh::load_from_file(path).map_err(|e| SceneError::new(path, None, &e))?;
+        if model.tris.is_empty() {
+            return Err(SceneError::new(path, None, "the file has no triangles"));
+        }
+        model.recenter();
+
+        let mut scene = Scene::new();
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        // A node for the triangles of each material
+        for (name, mesh, material) in Libraries::default().add_model(&mut scene, model, dir) {
+            let mut node = Node::new(&name, Transform::identity(), Some(mesh));
+            node.material = material;
+            scene.add_node(node, None);
+        }
+        scene.lights.push(Light::directional(Vector3::new(-1.0, -1.0, -1.0)));
+
+        Ok(Self {
+            scene,
+            camera: Camera::new(Vector3::zeros(), vector(default_direction())),
+            frame_camera: true,
+            background: color(default_background())
+        })
+    }
+
+    /// Loads and validates the scene file at the given path
+    pub fn load(path: &str) -> Result<Self, SceneError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| SceneError::new(path, None, &e.to_string()))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        Self::parse(&contents, path, dir)
+    }
+
+    /// Builds the scene described by the contents of a scene file
+    ///
+    /// `path` is only used for error messages, and `dir` is
+    /// where the paths in the file are relative to.
+    pub fn parse(contents: &str, path: &str, dir: &Path) -> Result<Self, SceneError> {
+        let desc: SceneDesc = toml::from_str(contents)
+            .map_err(|e| SceneError::new(path, None, &e.to_string()))?;
+        let error = |entry: String, message: &str| SceneError::new(path, Some(entry), message);
+
+        let mut scene = Scene::new();
+
+        // Lights
+        for (i, light) in desc.lights.iter().enumerate() {
+            let direction = vector(light.direction);
+            if direction.magnitude() == 0.0 {
+                return Err(error(format!("lights[{}]", i), "direction can't be zero"));
+            }
+            if light.intensity < 0.0 {
+                return Err(error(format!("lights[{}]", i), "intensity can't be negative"));
+            }
+            let base = match (light.position, light.angle) {
+                (Some(position), angle) => {
+                    let angle = angle.unwrap_or_else(default_spot_angle);
+                    if !(angle > 0.0 && angle < 90.0) {
+                        return Err(error(format!("lights[{}]", i), "angle has to be between 0 and 90 degrees"));
+                    }
+                    Light::spot(vector(position), direction, angle)
+                },
+                (None, Some(_)) => {
+                    return Err(error(format!("lights[{}]", i), "only spot lights, with a position, have an angle"));
+                },
+                (None, None) => Light::directional(direction)
+            };
+            scene.lights.push(Light {
+                color: color(light.color),
+                intensity: light.intensity,
+                shadows: light.shadows,
+                ..base
+            });
+        }
+
+        // Textures
+        let mut textures = HashMap::new();
+        for (i, texture) in desc.textures.iter().enumerate() {
+            let entry = format!("textures[{}] \"{}\"", i, texture.name);
+            if textures.contains_key(texture.name.as_str()) {
+                return Err(error(entry, "a texture with this name already exists"));
+            }
+            let texture_path = dir.join(&texture.path);
+            if !texture_path.is_file() {
+                return Err(error(entry, &format!("can't find the file {}", texture_path.display())));
+            }
+            let image = Texture::load(&texture_path.to_string_lossy()).map_err(|e| error(entry, &e))?;
+            textures.insert(texture.name.as_str(), scene.add_texture(image));
+        }
+
+        // Materials
+        let mut materials = HashMap::new();
+        for (i, material) in desc.materials.iter().enumerate() {
+            let entry = format!("materials[{}] \"{}\"", i, material.name);
+            if materials.contains_key(material.name.as_str()) {
+                return Err(error(entry, "a material with this name already exists"));
+            }
+            if !(0.0..=1.0).contains(&material.opacity) {
+                return Err(error(entry, "opacity has to be between 0 and 1"));
+            }
+            let blend = match material.blend.as_deref() {
+                None | Some("alpha") => BlendMode::Alpha,
+                Some("additive") => BlendMode::Additive,
+                Some("multiply") => BlendMode::Multiply,
+                Some(other) => return Err(error(
+                    entry, &format!("unknown blend mode \"{}\", expected alpha, additive or multiply", other)
+                ))
+            };
+            let find_texture = |name: &Option<String>| match name {
+                Some(name) => textures.get(name.as_str()).copied().map(Some)
+                    .ok_or_else(|| error(entry.clone(), &format!("unknown texture \"{}\"", name))),
+                None => Ok(None)
+            };
+            let texture = find_texture(&material.texture)?;
+            let normal_map = find_texture(&material.normal_map)?;
+            let index = scene.add_material(Material {
+                opacity: material.opacity,
+                blend,
+                texture,
+                normal_map,
+                ..Material::new(&material.name, color(material.color))
+            });
+            materials.insert(material.name.as_str(), index);
+        }
+
+        // Meshes
+        let mut meshes = HashMap::new();
+        let mut libraries = Libraries::default();
+        for (i, mesh) in desc.meshes.iter().enumerate() {
+            let entry = format!("meshes[{}] \"{}\"", i, mesh.name);
+            if meshes.contains_key(mesh.name.as_str()) {
+                return Err(error(entry, "a mesh with this name already exists"));
+            }
+            let mesh_path = dir.join(&mesh.path);
+            if !mesh_path.is_file() {
+                return Err(error(entry, &format!("can't find the file {}", mesh_path.display())));
+            }
+            let mut model = Mesh::load_from_file(&mesh_path.to_string_lossy())
+                .map_err(|e| error(entry.clone(), &format!("can't load {}: {}", mesh_path.display(), e)))?;
+            if model.tris.is_empty() {
+                return Err(error(entry, "the file has no triangles"));
+            }
+            if mesh.center {
+                model.recenter();
+            }
+            let model_dir = mesh_path.parent().unwrap_or(dir);
+            meshes.insert(mesh.name.as_str(), libraries.add_model(&mut scene, model, model_dir));
+        }
+
+        // Objects
+        let mut objects = HashMap::new();
+        for (i, object) in desc.objects.iter().enumerate() {
+            let entry = format!("objects[{}] \"{}\"", i, object.name);
+            if objects.contains_key(object.name.as_str()) {
+                return Err(error(entry, "an object with this name already exists"));
+            }
+            if object.scale.contains(&0.0) {
+                return Err(error(entry, "scale can't be zero"));
+            }
+
+            let parts = match &object.mesh {
+                Some(name) => meshes.get(name.as_str())
+                    .ok_or_else(|| error(entry.clone(), &format!("unknown mesh \"{}\"", name)))?
+                    .as_slice(),
+                None => &[]
+            };
+            let material = match &object.material {
+                Some(name) => Some(*materials.get(name.as_str())
+                    .ok_or_else(|| error(entry.clone(), &format!("unknown material \"{}\"", name)))?),
+                None => None
+            };
+            let parent = match &object.parent {
+                Some(name) => Some(*objects.get(name.as_str()).ok_or_else(|| error(
+                    entry.clone(),
+                    &format!("unknown parent \"{}\", parents have to be declared before their children", name)
+                ))?),
+                None => None
+            };
+
+            let rotation = vector(object.rotation).map(f32::to_radians);
+            let transform = Transform {
+                translation: vector(object.translation),
+                rotation: Transform::euler_rotation(rotation.x, rotation.y, rotation.z),
+                scale: vector(object.scale)
+            };
+            // A mesh split by the materials of its library gets a child
+            // node for each part, all overridden by the object's material
+            let (mesh, part_material) = match parts {
+                [(_, mesh, part_material)] => (Some(*mesh), *part_material),
+                _ => (None, None)
+            };
+            let mut node = Node::new(&object.name, transform, mesh);
+            node.material = material.or(part_material);
+            node.spin = vector(object.spin).map(f32::to_radians);
+            let index = scene.add_node(node, parent);
+            if parts.len() > 1 {
+                for (name, mesh, part_material) in parts {
+                    let mut child = Node::new(&format!("{}/{}", object.name, name), Transform::identity(), Some(*mesh));
+                    child.material = material.or(*part_material);
+                    scene.add_node(child, Some(index));
+                }
+            }
+            objects.insert(object.name.as_str(), index);
+        }
+
+        // Camera
+        let camera_desc = desc.camera.unwrap_or(CameraDesc {
+            position: None,
+            direction: default_direction(),
+            fov: default_fov(),
+            near: None,
+            far: None
+        });
+        let direction = vector(camera_desc.direction);
+        if direction.magnitude() == 0.0 {
+            return Err(error("camera".to_string(), "direction can't be zero"));
+        }
+        if !(camera_desc.fov > 0.0 && camera_desc.fov < 180.0) {
+            return Err(error("camera".to_string(), "fov has to be between 0 and 180 degrees"));
+        }
+        let mut camera = Camera::new(vector(camera_desc.position.unwrap_or_default()), direction);
+        camera.fov = camera_desc.fov;
+        camera.near = camera_desc.near.unwrap_or(camera.near);
+        camera.far = camera_desc.far.unwrap_or(camera.far);
+        if camera.near <= 0.0 || camera.far <= camera.near {
+            return Err(error("camera".to_string(), "near has to be positive and smaller than far"));
+        }
+
+        Ok(Self {
+            scene,
+            camera,
+            frame_camera: camera_desc.position.is_none(),
+            background: color(desc.background)
+        })
+    }
+}
+
+impl SceneError {
+    fn new(path: &str, entry: Option<String>, message: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            entry,
+            message: message.to_string()
+        }
+    }
+}
+
+fn vector(v: [f32; 3]) -> Vector3<f32> {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+fn color(c: [u8; 3]) -> Color {
+    Color::RGB(c[0], c[1], c[2])
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::LightKind;
+
+    fn parse(contents: &str) -> Result<SceneFile, SceneError> {
+        SceneFile::parse(contents, "test.toml", Path::new("assets"))
+    }
+
+    /// A directory of files for a single test, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        /// Creates the directory with the given files, unique to the test
+        /// and the process so parallel runs don't share it
+        fn new(test: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("scene_file_{}_{}", test, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            for (name, contents) in files {
+                fs::write(dir.join(name), contents).unwrap();
+            }
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let file = parse(r#"
+            [[lights]]
+            direction = [0.0, -1.0, 0.0]
+
+            [[lights]]
+            direction = [0.0, -1.0, 0.0]
+            position = [0.0, 5.0, 0.0]
+            angle = 20.0
+            shadows = false
+
+            [[materials]]
+            name = "red"
+            color = [255, 0, 0]
+
+            [[materials]]
+            name = "smoke"
+            opacity = 0.5
+            blend = "multiply"
+
+            [[objects]]
+            name = "pivot"
+            spin = [0.0, 90.0, 0.0]
+
+            [[objects]]
+            name = "child"
+            parent = "pivot"
+            material = "red"
+            translation = [1.0, 0.0, 0.0]
+        "#).unwrap();
+
+        assert!(file.frame_camera);
+        assert_eq!(file.scene.lights.len(), 2);
+        assert_eq!(file.scene.lights[1].kind, LightKind::Spot { position: Vector3::new(0.0, 5.0, 0.0), angle: 20.0 });
+        assert!(file.scene.lights[0].shadows && !file.scene.lights[1].shadows);
+        assert_eq!(file.scene.nodes.len(), 2);
+        assert_eq!(file.scene.nodes[0].children, vec![1]);
+        assert_eq!(file.scene.nodes[1].material, Some(0));
+        assert!(!file.scene.materials[0].is_transparent());
+        assert_eq!(file.scene.materials[1].opacity, 0.5);
+        assert_eq!(file.scene.materials[1].blend, BlendMode::Multiply);
+    }
+
+    #[test]
+    fn test_default_scene() {
+        let file = SceneFile::load("assets/scene.toml").unwrap();
+        assert_eq!(file.scene.meshes.len(), 1);
+        assert_eq!(file.scene.instances().len(), 3);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = parse(r#"
+            [[objects]]
+            name = "a"
+
+            [[objects]]
+            name = "b"
+            mesh = "missing"
+        "#).err().unwrap();
+        assert_eq!(error.entry.as_deref(), Some("objects[1] \"b\""));
+        assert!(error.message.contains("unknown mesh"));
+
+        let error = parse(r#"
+            [camera]
+            fov = 200.0
+        "#).err().unwrap();
+        assert_eq!(error.entry.as_deref(), Some("camera"));
+
+        let error = parse(r#"
+            [[lights]]
+            direction = [0.0, -1.0, 0.0]
+            angle = 20.0
+        "#).err().unwrap();
+        assert_eq!(error.entry.as_deref(), Some("lights[0]"));
+
+        let error = parse(r#"
+            [[materials]]
+            name = "glass"
+            blend = "screen"
+        "#).err().unwrap();
+        assert_eq!(error.entry.as_deref(), Some("materials[0] \"glass\""));
+        assert!(error.message.contains("unknown blend mode"));
+
+        let error = parse(r#"
+            [[materials]]
+            name = "floor"
+            texture = "checker"
+        "#).err().unwrap();
+        assert!(error.message.contains("unknown texture"));
+
+        let error = parse(r#"
+            [[materials]]
+            name = "floor"
+            normal_map = "tiles"
+        "#).err().unwrap();
+        assert!(error.message.contains("unknown texture \"tiles\""));
+
+        let error = parse(r#"
+            [[textures]]
+            name = "checker"
+            path = "missing.png"
+        "#).err().unwrap();
+        assert_eq!(error.entry.as_deref(), Some("textures[0] \"checker\""));
+
+        let error = parse("[[objects]]\nname = 3").err().unwrap();
+        assert!(error.entry.is_none());
+
+        // A truncated model is an error of its mesh, not a crash
+        let dir = TempDir::new("errors", &[("truncated.obj", "v 0 0 0\nv 1 0 0\nv 0 1\nf 1 2 3\n")]);
+        let error = SceneFile::parse(r#"
+            [[meshes]]
+            name = "broken"
+            path = "truncated.obj"
+        "#, "test.toml", &dir.0).err().unwrap();
+        assert_eq!(error.entry.as_deref(), Some("meshes[0] \"broken\""));
+        assert!(error.message.contains("line 4: no vertex 3"));
+    }
+
+    #[test]
+    fn test_mesh_library() {
+        let dir = TempDir::new("mesh_library", &[
+            ("parts.mtl", "newmtl red\nKd 1 0 0\nnewmtl glass\nd 0.5\n"),
+            ("parts.obj", "mtllib parts.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\nusemtl glass\nf 1 3 2\n")
+        ]);
+        let file = SceneFile::parse(r#"
+            [[materials]]
+            name = "gold"
+
+            [[meshes]]
+            name = "parts"
+            path = "parts.obj"
+
+            [[meshes]]
+            name = "centered parts"
+            path = "parts.obj"
+            center = true
+
+            [[objects]]
+            name = "plain"
+            mesh = "parts"
+
+            [[objects]]
+            name = "gilded"
+            mesh = "parts"
+            material = "gold"
+        "#, "test.toml", &dir.0).unwrap();
+
+        // Each object gets a child for each material of the library
+        let scene = &file.scene;
+        let names: Vec<&str> = scene.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, vec!["plain", "plain/red", "plain/glass", "gilded", "gilded/red", "gilded/glass"]);
+        assert_eq!(scene.nodes[0].children, vec![1, 2]);
+        assert_eq!(scene.nodes[0].mesh, None);
+        let material = |node: usize| &scene.materials[scene.nodes[node].material.unwrap()];
+        assert_eq!(material(1).color, Color::RGB(255, 0, 0));
+        assert_eq!(material(2).opacity, 0.5);
+        assert_eq!(material(4).name, "gold");
+        assert_eq!(material(5).name, "gold");
+
+        // Meshes sharing a library share its materials
+        assert_eq!(scene.materials.len(), 3);
+        assert_eq!(scene.meshes.len(), 4);
+    }
+}
@@ -0,0 +1,78 @@
+/*!
+# screenshot.rs
+
+Saves the frame shown in the window to a PNG image named after the
+time it was taken, and optionally its depth buffer in grayscale.
+*/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::camera::Camera;
+use crate::framebuffer::{self, Framebuffer};
+
+/// Saves the framebuffer to a timestamped PNG in the directory,
+/// returning the paths written
+///
+/// The depth image, if asked for, is saved next to it with a `_depth` suffix.
+pub fn save(framebuffer: &Framebuffer, camera: &Camera, directory: &str, depth: bool) -> Result<Vec<String>, String> {
+    std::fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory, e))?;
+
+    let path = format!("{}/screenshot_{}.png", directory.trim_end_matches('/'), timestamp(SystemTime::now()));
+    framebuffer.save(&path)?;
+    let depth_path = framebuffer::with_suffix(&path, "_depth");
+    let mut written = vec![path];
+    if depth {
+        framebuffer.save_depth(&depth_path, camera.near, camera.far)?;
+        written.push(depth_path);
+    }
+    Ok(written)
+}
+
+/// UTC date and time down to the millisecond, usable in a file name
+///
+/// Like `2024-03-09_17-05-42.108`, so names sort by the time they were taken.
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}.{:03}",
+        year, month, day,
+        seconds_of_day / 3600, seconds_of_day / 60 % 60, seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Year, month and day of a count of days since 1970-01-01
+///
+/// Howard Hinnant's algorithm, working in 400 year eras starting on March 1st.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01_00-00-00.000");
+        // 2024-02-29 13:37:05.250, a leap day
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_213_825_250);
+        assert_eq!(timestamp(time), "2024-02-29_13-37-05.250");
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
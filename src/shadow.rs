@@ -0,0 +1,241 @@
+/*!
+# shadow.rs
+
+Shadows cast by the lights, with shadow maps.
+
+The scene is rendered from every light casting shadows with the same
+rasterizer as the frame, keeping only the depth: through an orthographic
+projection around the whole scene for directional lights, and through a
+perspective one covering the cone of spot lights. A point is in shadow
+when something is closer to the light than it in the map.
+
+The depths are compared as distances along the direction of the light.
+The point is pushed out along its normal and compared with a bias, both
+of about the size of a texel, the bias growing on surfaces sloping away
+from the light, so they don't shadow themselves (shadow acne). The
+texels around the point are compared too and averaged (percentage
+closer filtering), softening the edges of the shadows.
+
+The triangles are projected per object, and the map filled in tiles,
+in parallel, giving the same map as a single thread.
+
+Transparent objects don't cast shadows.
+*/
+
+use na::{Matrix4, Vector3, Vector4};
+
+use crate::framebuffer::Framebuffer;
+use crate::frustum::Frustum;
+use crate::lighting::{Light, LightKind};
+use crate::linear_transforms;
+use crate::parallel;
+use crate::polygons::Tri;
+use crate::raster;
+use crate::render::to_screen;
+use crate::scene::{Instance, Scene};
+
+/// Width and height of the shadow maps, in texels
+pub const SHADOW_MAP_SIZE: usize = 1024;
+/// Width and height of the tiles of the maps filled in parallel, in texels
+const TILE_SIZE: usize = 128;
+/// Texels on each side of the point compared with it, 1 for 3x3 texels
+const PCF_RADIUS: i64 = 1;
+/// Distance a point has to be behind the depth in the map to be in
+/// shadow, in texels
+const DEPTH_BIAS: f32 = 1.5;
+/// Steepest slope of a surface seen from the light the bias grows with
+const MAX_SLOPE: f32 = 10.0;
+/// Distance a point is pushed out along its normal before looking it
+/// up, in texels
+const NORMAL_OFFSET: f32 = 1.0;
+
+/// Depth of the scene as seen from a light
+pub struct ShadowMap {
+    /// Matrix moving the world into the light's point of view
+    view: Matrix4<f32>,
+    projection: Matrix4<f32>,
+    size: usize,
+    /// Distance along the light's direction to the closest surface in each
+    /// texel, infinite where there is none
+    depth: Vec<f32>,
+    /// Width of a texel in world units, at a distance of 1 for spot lights
+    texel: f32,
+    /// Whether texels get wider with the distance, for spot lights
+    perspective: bool
+}
+
+impl ShadowMap {
+    /// Renders the depth of the opaque objects of the scene seen from
+    /// the light, into a map `size` texels wide, with up to `threads`
+    /// threads
+    pub fn render(light: &Light, scene: &Scene, size: usize, threads: usize) -> Self {
+        let sphere = scene.bounding_sphere();
+        let radius = sphere.radius.max(f32::EPSILON);
+        let up = if light.direction.y.abs() < 0.99 { Vector3::y() } else { Vector3::z() };
+
+        // Around the whole scene, or the part of it in the cone
+        let (view, projection, near, far, texel, perspective) = match light.kind {
+            LightKind::Directional => {
+                let position = sphere.center - light.direction * radius * 2.0;
+                let view = linear_transforms::view_matrix(position, light.direction, up);
+                let (near, far) = (radius, radius * 3.0);
+                let projection = linear_transforms::orthographic_matrix(radius, radius, near, far);
+                (view, projection, near, far, 2.0 * radius / size as f32, false)
+            },
+            LightKind::Spot { position, angle } => {
+                let view = linear_transforms::view_matrix(position, light.direction, up);
+                let distance = (sphere.center - position).magnitude();
+                let (near, far) = ((distance - radius).max(radius * 0.01), distance + radius);
+                let projection = linear_transforms::projection_matrix(angle * 2.0, 1.0, near, far);
+                (view, projection, near, far, 2.0 * angle.to_radians().tan() / size as f32, true)
+            }
+        };
+
+        // The opaque objects in view of the light, on the map
+        let frustum = Frustum::from_matrix(&(projection * view));
+        let instances: Vec<Instance> = scene.instances().into_iter()
+            .filter(|instance| {
+                let material = instance.material.map(|m| &scene.materials[m]);
+                let bounds = scene.mesh_bounds[instance.mesh].transform(&instance.world_matrix);
+                !material.is_some_and(|material| material.is_transparent()) && frustum.intersects_sphere(&bounds)
+            })
+            .collect();
+        let tris: Vec<Tri> = parallel::map(&instances, threads, |instance| {
+            let view_matrix = view * instance.world_matrix;
+            let mut tris = Vec::new();
+            for triangle in scene.meshes[instance.mesh].tris.iter() {
+                // Both sides cast shadows, cut off behind the near plane
+                let viewed = view_matrix * *triangle;
+                for clipped in viewed.clip_against_plane(Vector3::new(0.0, 0.0, near), Vector3::z()) {
+                    let mut projected = projection * clipped;
+                    for p in projected.p.iter_mut() {
+                        *p = to_screen(*p, size as f32, size as f32);
+                    }
+                    tris.push(projected);
+                }
+            }
+            tris
+        }).into_iter().flatten().collect();
+
+        // Each tile filled with the triangles over it, in order
+        let mut framebuffer = Framebuffer::new(size, size);
+        let columns = size.div_ceil(TILE_SIZE);
+        let tiles: Vec<(usize, usize)> = (0..columns * columns)
+            .map(|tile| (tile % columns * TILE_SIZE, tile / columns * TILE_SIZE))
+            .collect();
+        let filled = parallel::map(&tiles, threads, |&(x, y)| {
+            let (width, height) = (TILE_SIZE.min(size - x), TILE_SIZE.min(size - y));
+            let mut tile = framebuffer.tile(x, y, width, height);
+            for tri in tris.iter() {
+                let (min, max) = raster::pixel_bounds(tri, size, size);
+                if min.0 < x + width && max.0 > x && min.1 < y + height && max.1 > y {
+                    raster::fill_triangle(&mut tile, tri);
+                }
+            }
+            tile
+        });
+        for tile in filled.iter() {
+            framebuffer.put_tile(tile);
+        }
+
+        // Back from the depth of the projection to distances
+        let depth = framebuffer.depth.iter().map(|&z| match z {
+            z if !z.is_finite() => f32::INFINITY,
+            z if perspective => near * far / (far - z * (far - near)),
+            z => near + z * (far - near)
+        }).collect();
+        Self { view, projection, size, depth, texel, perspective }
+    }
+
+    /// Fraction of the light reaching the point on a surface with the
+    /// given normal, from 0 in full shadow to 1 in full light
+    pub fn visibility(&self, point: &Vector3<f32>, normal: &Vector3<f32>) -> f32 {
+        let distance = (self.view * Vector4::new(point.x, point.y, point.z, 1.0)).z;
+        let texel = if self.perspective { self.texel * distance } else { self.texel };
+        let pushed = point + normal * texel * NORMAL_OFFSET;
+        let viewed = self.view * Vector4::new(pushed.x, pushed.y, pushed.z, 1.0);
+        // Behind a spot light, where it doesn't shine anyway
+        if viewed.z <= 0.0 {
+            return 1.0;
+        }
+        let p = to_screen(self.projection * viewed, self.size as f32, self.size as f32);
+
+        // The surface moves away from the light this much per texel, over
+        // every texel compared
+        let normal_view = self.view.fixed_slice::<3, 3>(0, 0) * normal;
+        let cos = normal_view.z.abs();
+        let slope = ((1.0 - cos * cos).sqrt() / cos).min(MAX_SLOPE);
+        let bias = texel * (DEPTH_BIAS + slope * (PCF_RADIUS + 1) as f32);
+
+        // Compare with the texels around, nothing casts shadows off the map
+        let (x, y) = (p.x.floor() as i64, p.y.floor() as i64);
+        let size = self.size as i64;
+        let depth = viewed.z - bias;
+        let mut lit = 0;
+        for ty in y - PCF_RADIUS..=y + PCF_RADIUS {
+            for tx in x - PCF_RADIUS..=x + PCF_RADIUS {
+                let outside = tx < 0 || ty < 0 || tx >= size || ty >= size;
+                if outside || depth <= self.depth[(ty * size + tx) as usize] {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f32 / ((2 * PCF_RADIUS + 1) * (2 * PCF_RADIUS + 1)) as f32
+    }
+}
+
+/// Shadow maps of the lights of the scene, `None` for the lights not
+/// casting shadows, each rendered with up to `threads` threads
+pub fn render_shadow_maps(scene: &Scene, size: usize, threads: usize) -> Vec<Option<ShadowMap>> {
+    scene.lights.iter()
+        .map(|light| light.shadows.then(|| ShadowMap::render(light, scene, size, threads)))
+        .collect()
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polygons::Mesh;
+    use crate::scene::Node;
+    use crate::transform::Transform;
+
+    /// A square of the given half size in the xz plane at the height
+    fn square(half: f32, height: f32) -> Vec<Tri> {
+        vec![
+            Tri::from([-half, height, -half, half, height, half, half, height, -half]),
+            Tri::from([-half, height, -half, -half, height, half, half, height, half])
+        ]
+    }
+
+    #[test]
+    fn test_shadow_map() {
+        // A small square floating over a big one, lit from straight above
+        let mut scene = Scene::new();
+        let ground = scene.add_mesh(Mesh::new(square(4.0, 0.0)));
+        let roof = scene.add_mesh(Mesh::new(square(1.0, 1.0)));
+        scene.add_node(Node::new("ground", Transform::identity(), Some(ground)), None);
+        scene.add_node(Node::new("roof", Transform::identity(), Some(roof)), None);
+        let up = Vector3::y();
+
+        for light in [
+            Light::directional(-up),
+            Light::spot(Vector3::new(0.0, 5.0, 0.0), -up, 60.0)
+        ] {
+            let map = ShadowMap::render(&light, &scene, 256, 4);
+            // The same map in tiles as on a single thread
+            assert!(map.depth == ShadowMap::render(&light, &scene, 256, 1).depth);
+            // Under the roof, out of it, and on the roof, which doesn't shadow itself
+            assert_eq!(map.visibility(&Vector3::new(0.0, 0.0, 0.2), &up), 0.0);
+            assert_eq!(map.visibility(&Vector3::new(3.0, 0.0, -2.5), &up), 1.0);
+            assert_eq!(map.visibility(&Vector3::new(0.5, 1.0, 0.5), &up), 1.0);
+
+            // Partly lit at the edge of the shadow, further out for the spot light
+            let edge = (0..100)
+                .map(|i| map.visibility(&Vector3::new(0.9 + i as f32 * 0.005, 0.0, 0.0), &up))
+                .collect::<Vec<_>>();
+            assert!(edge.iter().any(|&v| v > 0.0 && v < 1.0));
+        }
+    }
+}
@@ -0,0 +1,250 @@
+/*!
+# simd.rs
+
+Four wide vectors of `f32` for the hot loops of the renderer, and
+vertices stored as a structure of arrays to transform 4 at a time.
+
+On x86_64 the vectors use SSE, which every x86_64 processor has, and
+elsewhere they fall back to plain arrays, which the compiler can still
+vectorize. Each lane goes through the same operations in the same
+order as the scalar code, so both give the same results.
+*/
+
+use na::{Matrix4, Vector4};
+
+use crate::polygons::Tri;
+
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+mod lanes {
+    use std::arch::x86_64::*;
+    use std::ops::{Add, Div, Mul, Sub};
+
+    /// Four `f32` in an SSE register
+    #[derive(Clone, Copy, Debug)]
+    pub struct F32x4(__m128);
+
+    // SSE2 is part of x86_64, so the intrinsics are always available
+    impl F32x4 {
+        pub fn new(a: f32, b: f32, c: f32, d: f32) -> Self {
+            unsafe { Self(_mm_setr_ps(a, b, c, d)) }
+        }
+
+        pub fn splat(value: f32) -> Self {
+            unsafe { Self(_mm_set1_ps(value)) }
+        }
+
+        pub fn to_array(self) -> [f32; 4] {
+            let mut array = [0.0; 4];
+            unsafe { _mm_storeu_ps(array.as_mut_ptr(), self.0) };
+            array
+        }
+
+        /// Bit mask of the lanes greater than the other's
+        pub fn gt_mask(self, other: Self) -> u32 {
+            unsafe { _mm_movemask_ps(_mm_cmpgt_ps(self.0, other.0)) as u32 }
+        }
+
+        /// Bit mask of the lanes equal to the other's
+        pub fn eq_mask(self, other: Self) -> u32 {
+            unsafe { _mm_movemask_ps(_mm_cmpeq_ps(self.0, other.0)) as u32 }
+        }
+    }
+
+    impl Add for F32x4 {
+        type Output = Self;
+
+        fn add(self, other: Self) -> Self {
+            unsafe { Self(_mm_add_ps(self.0, other.0)) }
+        }
+    }
+
+    impl Sub for F32x4 {
+        type Output = Self;
+
+        fn sub(self, other: Self) -> Self {
+            unsafe { Self(_mm_sub_ps(self.0, other.0)) }
+        }
+    }
+
+    impl Mul for F32x4 {
+        type Output = Self;
+
+        fn mul(self, other: Self) -> Self {
+            unsafe { Self(_mm_mul_ps(self.0, other.0)) }
+        }
+    }
+
+    impl Div for F32x4 {
+        type Output = Self;
+
+        fn div(self, other: Self) -> Self {
+            unsafe { Self(_mm_div_ps(self.0, other.0)) }
+        }
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "sse2")))]
+mod lanes {
+    use std::ops::{Add, Div, Mul, Sub};
+
+    /// Four `f32` in an array, lane by lane
+    #[derive(Clone, Copy, Debug)]
+    pub struct F32x4([f32; 4]);
+
+    impl F32x4 {
+        pub fn new(a: f32, b: f32, c: f32, d: f32) -> Self {
+            Self([a, b, c, d])
+        }
+
+        pub fn splat(value: f32) -> Self {
+            Self([value; 4])
+        }
+
+        pub fn to_array(self) -> [f32; 4] {
+            self.0
+        }
+
+        /// Bit mask of the lanes greater than the other's
+        pub fn gt_mask(self, other: Self) -> u32 {
+            (0..4).filter(|&i| self.0[i] > other.0[i]).map(|i| 1 << i).sum()
+        }
+
+        /// Bit mask of the lanes equal to the other's
+        pub fn eq_mask(self, other: Self) -> u32 {
+            (0..4).filter(|&i| self.0[i] == other.0[i]).map(|i| 1 << i).sum()
+        }
+
+        fn zip(self, other: Self, f: impl Fn(f32, f32) -> f32) -> Self {
+            Self([0, 1, 2, 3].map(|i| f(self.0[i], other.0[i])))
+        }
+    }
+
+    impl Add for F32x4 {
+        type Output = Self;
+
+        fn add(self, other: Self) -> Self {
+            self.zip(other, |a, b| a + b)
+        }
+    }
+
+    impl Sub for F32x4 {
+        type Output = Self;
+
+        fn sub(self, other: Self) -> Self {
+            self.zip(other, |a, b| a - b)
+        }
+    }
+
+    impl Mul for F32x4 {
+        type Output = Self;
+
+        fn mul(self, other: Self) -> Self {
+            self.zip(other, |a, b| a * b)
+        }
+    }
+
+    impl Div for F32x4 {
+        type Output = Self;
+
+        fn div(self, other: Self) -> Self {
+            self.zip(other, |a, b| a / b)
+        }
+    }
+}
+
+pub use lanes::F32x4;
+
+/// Vertices as a structure of arrays, one array per coordinate, padded
+/// to a multiple of 4 so they can be read 4 at a time
+pub struct Vertices {
+    x: Vec<f32>,
+    y: Vec<f32>,
+    z: Vec<f32>,
+    w: Vec<f32>
+}
+
+impl Vertices {
+    /// The vertices of the triangles, 3 after 3
+    pub fn from_tris(tris: &[Tri]) -> Self {
+        let count = (tris.len() * 3).div_ceil(4) * 4;
+        let mut vertices = Self {
+            x: Vec::with_capacity(count),
+            y: Vec::with_capacity(count),
+            z: Vec::with_capacity(count),
+            w: Vec::with_capacity(count)
+        };
+        for p in tris.iter().flat_map(|tri| tri.p.iter()) {
+            vertices.x.push(p.x);
+            vertices.y.push(p.y);
+            vertices.z.push(p.z);
+            vertices.w.push(p.w);
+        }
+        for coordinate in [&mut vertices.x, &mut vertices.y, &mut vertices.z, &mut vertices.w] {
+            coordinate.resize(count, 0.0);
+        }
+        vertices
+    }
+
+    /// The vertices multiplied by the matrix, 4 at a time
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let count = self.x.len();
+        let mut transformed = Self {
+            x: vec![0.0; count],
+            y: vec![0.0; count],
+            z: vec![0.0; count],
+            w: vec![0.0; count]
+        };
+        let m = |row: usize, column: usize| F32x4::splat(matrix[(row, column)]);
+        let rows = [0, 1, 2, 3].map(|row| [m(row, 0), m(row, 1), m(row, 2), m(row, 3)]);
+
+        let load = |coordinate: &[f32], i: usize| F32x4::new(
+            coordinate[i], coordinate[i + 1], coordinate[i + 2], coordinate[i + 3]
+        );
+        for i in (0..count).step_by(4) {
+            let (x, y, z, w) = (load(&self.x, i), load(&self.y, i), load(&self.z, i), load(&self.w, i));
+            let outputs = [&mut transformed.x, &mut transformed.y, &mut transformed.z, &mut transformed.w];
+            for (row, output) in rows.iter().zip(outputs) {
+                let result = row[0] * x + row[1] * y + row[2] * z + row[3] * w;
+                output[i..i + 4].copy_from_slice(&result.to_array());
+            }
+        }
+        transformed
+    }
+
+    /// The vertex at the index
+    pub fn get(&self, i: usize) -> Vector4<f32> {
+        Vector4::new(self.x[i], self.y[i], self.z[i], self.w[i])
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_transforms;
+
+    #[test]
+    fn test_lanes() {
+        let a = F32x4::new(1.0, -2.0, 0.0, 4.0);
+        let b = F32x4::splat(2.0);
+        assert_eq!((a * b + b).to_array(), [4.0, -2.0, 2.0, 10.0]);
+        assert_eq!(((a - b) / b).to_array(), [-0.5, -2.0, -1.0, 1.0]);
+        assert_eq!(a.gt_mask(F32x4::splat(0.0)), 0b1001);
+        assert_eq!(a.eq_mask(F32x4::splat(0.0)), 0b0100);
+    }
+
+    #[test]
+    fn test_transform() {
+        let tris = [
+            Tri::from([1.0, 2.0, 3.0, -4.0, 5.0, 6.0, 7.0, -8.0, 9.0]),
+            Tri::from([0.5, 0.0, -1.5, 2.0, 2.0, 2.0, -3.0, 1.0, 0.0])
+        ];
+        let matrix = linear_transforms::rotation_matrix_y(0.7)
+            * linear_transforms::translation_matrix(1.0, -2.0, 3.0);
+        let transformed = Vertices::from_tris(&tris).transform(&matrix);
+        for (i, p) in tris.iter().flat_map(|tri| tri.p.iter()).enumerate() {
+            assert!((transformed.get(i) - matrix * p).norm() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+/*!
+# texture.rs
+
+Images mapped onto the triangles by their texture coordinates, and how
+they are filtered.
+
+Each texture keeps a chain of mipmaps, every level half the size of the
+one before, each texel the average of 2x2 texels of the level above,
+down to a single texel. How many texels of the full size image a pixel
+covers comes from how fast the texture coordinates change between the
+pixels of its 2x2 quad, and its base 2 logarithm is the level of detail:
+the level whose texels are about the size of the pixel. Sampling from
+that level instead of the full image keeps far away textures from
+aliasing into noise.
+
+- Nearest takes the closest texel of the full image, aliasing at a distance.
+- Bilinear mixes the 4 closest texels, from the closest level.
+- Trilinear mixes the bilinear samples of the two closest levels, so
+  the level changes smoothly across the surface.
+- Anisotropic takes trilinear samples along the longest side of the
+  pixel's footprint, with the level of its shortest side, keeping
+  surfaces seen at grazing angles sharp.
+
+Texture coordinates repeat outside of [0, 1], and v goes up the image.
+*/
+
+use na::Vector2;
+use sdl2::image::LoadSurface;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::surface::Surface;
+
+/// How textures are sampled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+    Trilinear,
+    /// Trilinear with up to this many samples along the footprint of the pixel
+    Anisotropic(usize)
+}
+
+impl TextureFilter {
+    /// Most samples the anisotropic filter can take
+    pub const ANISOTROPY: [usize; 4] = [2, 4, 8, 16];
+
+    /// The filter after this one, from nearest to anisotropic with more
+    /// and more samples
+    pub fn next(self) -> Self {
+        match self {
+            TextureFilter::Nearest => TextureFilter::Bilinear,
+            TextureFilter::Bilinear => TextureFilter::Trilinear,
+            TextureFilter::Trilinear => TextureFilter::Anisotropic(2),
+            TextureFilter::Anisotropic(16) => TextureFilter::Nearest,
+            TextureFilter::Anisotropic(samples) => TextureFilter::Anisotropic(samples * 2)
+        }
+    }
+}
+
+/// A level of the mipmap chain
+struct Level {
+    width: usize,
+    height: usize,
+    /// Red, green and blue of each texel, row by row from the top left
+    texels: Vec<[f32; 3]>
+}
+
+/// An image with its mipmaps
+pub struct Texture {
+    /// From the full size image to a single texel
+    levels: Vec<Level>
+}
+
+impl Level {
+    /// Texel at the coordinates, repeating the image
+    fn texel(&self, x: i64, y: i64) -> [f32; 3] {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.texels[y * self.width + x]
+    }
+
+    /// Texel the texture coordinates fall in
+    fn nearest(&self, uv: Vector2<f32>) -> [f32; 3] {
+        let (x, y) = (uv.x * self.width as f32, (1.0 - uv.y) * self.height as f32);
+        self.texel(x.floor() as i64, y.floor() as i64)
+    }
+
+    /// The 4 texels around the texture coordinates, mixed by how close
+    /// their centers are
+    fn bilinear(&self, uv: Vector2<f32>) -> [f32; 3] {
+        let (x, y) = (uv.x * self.width as f32 - 0.5, (1.0 - uv.y) * self.height as f32 - 0.5);
+        let (left, top) = (x.floor(), y.floor());
+        let (fx, fy) = (x - left, y - top);
+        let (left, top) = (left as i64, top as i64);
+        let row = |y: i64| lerp(self.texel(left, y), self.texel(left + 1, y), fx);
+        lerp(row(top), row(top + 1), fy)
+    }
+
+    /// The next level of the chain, half the size, rounding down
+    fn downsample(&self) -> Level {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // Odd sizes repeat their last row or column
+                let mut sum = [0.0; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (sx, sy) = ((2 * x + dx).min(self.width - 1), (2 * y + dy).min(self.height - 1));
+                    let texel = self.texels[sy * self.width + sx];
+                    for c in 0..3 {
+                        sum[c] += texel[c] * 0.25;
+                    }
+                }
+                texels.push(sum);
+            }
+        }
+        Level { width, height, texels }
+    }
+}
+
+impl Texture {
+    /// A texture from RGB24 pixels, row by row from the top left, with
+    /// its mipmaps
+    pub fn new(width: usize, height: usize, pixels: &[u8]) -> Self {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height * 3, "wrong size of texture");
+        let texels = pixels.chunks_exact(3).map(|p| [p[0] as f32, p[1] as f32, p[2] as f32]).collect();
+        let mut levels = vec![Level { width, height, texels }];
+        while levels.last().is_some_and(|level| level.width > 1 || level.height > 1) {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// Loads an image file, in any format SDL_image reads
+    pub fn load(path: &str) -> Result<Self, String> {
+        let surface = Surface::from_file(path)?.convert_format(PixelFormatEnum::RGB24)?;
+        let (width, height, pitch) = (surface.width() as usize, surface.height() as usize, surface.pitch() as usize);
+        let pixels = surface.with_lock(|data| {
+            (0..height).flat_map(|y| data[y * pitch..y * pitch + width * 3].iter().copied()).collect::<Vec<u8>>()
+        });
+        Ok(Self::new(width, height, &pixels))
+    }
+
+    /// Number of levels of the mipmap chain, the full image included
+    #[allow(dead_code)]
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Color of the texture at the texture coordinates, for a pixel over
+    /// which they change by `dx` to the next pixel on the right and by
+    /// `dy` to the next one down
+    pub fn sample(&self, uv: Vector2<f32>, dx: Vector2<f32>, dy: Vector2<f32>, filter: TextureFilter) -> Color {
+        // Sides of the footprint of the pixel, in texels of the full image
+        let size = Vector2::new(self.levels[0].width as f32, self.levels[0].height as f32);
+        let (x_side, y_side) = (dx.component_mul(&size).magnitude(), dy.component_mul(&size).magnitude());
+
+        let texel = match filter {
+            TextureFilter::Nearest => self.levels[0].nearest(uv),
+            TextureFilter::Bilinear => {
+                let level = self.level_of_detail(x_side.max(y_side)).round() as usize;
+                self.levels[level].bilinear(uv)
+            },
+            TextureFilter::Trilinear => self.trilinear(uv, self.level_of_detail(x_side.max(y_side))),
+            TextureFilter::Anisotropic(max_samples) => {
+                let (axis, major, minor) = if x_side > y_side { (dx, x_side, y_side) } else { (dy, y_side, x_side) };
+                let samples = (major / minor.max(f32::EPSILON)).ceil().clamp(1.0, max_samples as f32);
+                let level = self.level_of_detail(major / samples);
+                let mut sum = [0.0; 3];
+                for i in 0..samples as usize {
+                    let offset = (i as f32 + 0.5) / samples - 0.5;
+                    let texel = self.trilinear(uv + axis * offset, level);
+                    for c in 0..3 {
+                        sum[c] += texel[c] / samples;
+                    }
+                }
+                sum
+            }
+        };
+        let channel = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+        Color::RGB(channel(texel[0]), channel(texel[1]), channel(texel[2]))
+    }
+
+    /// Level of the mipmap chain whose texels are the size of a footprint
+    /// that many texels of the full image wide, fractional between levels
+    fn level_of_detail(&self, footprint: f32) -> f32 {
+        let level = footprint.log2();
+        if level.is_nan() {
+            return 0.0;
+        }
+        level.clamp(0.0, (self.levels.len() - 1) as f32)
+    }
+
+    /// Bilinear samples of the two levels around the level of detail,
+    /// mixed by how close it is to each
+    fn trilinear(&self, uv: Vector2<f32>, level: f32) -> [f32; 3] {
+        let first = level.floor() as usize;
+        let upper = self.levels[first].bilinear(uv);
+        if first + 1 == self.levels.len() {
+            return upper;
+        }
+        lerp(upper, self.levels[first + 1].bilinear(uv), level - first as f32)
+    }
+}
+
+/// Mixes two colors, from `a` at 0 to `b` at 1
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+/// Multiplies a color by the color of a texture, white leaving it as is
+pub fn modulate(color: Color, texel: Color) -> Color {
+    let mix = |a: u8, b: u8| ((a as u32 * b as u32 + 127) / 255) as u8;
+    Color::RGBA(mix(color.r, texel.r), mix(color.g, texel.g), mix(color.b, texel.b), color.a)
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A checkerboard of black and white texels, `size` texels wide
+    fn checkerboard(size: usize) -> Texture {
+        let pixels: Vec<u8> = (0..size * size)
+            .flat_map(|i| [if (i % size + i / size).is_multiple_of(2) { 255 } else { 0 }; 3])
+            .collect();
+        Texture::new(size, size, &pixels)
+    }
+
+    #[test]
+    fn test_mipmaps() {
+        // Halving down to a single texel, odd sizes rounding down,
+        // and every level keeps the average color
+        assert_eq!(checkerboard(16).levels(), 5);
+        assert_eq!(Texture::new(5, 3, &[0; 45]).levels(), 3);
+        let texture = checkerboard(16);
+        assert!(texture.levels.iter().skip(1).all(|level| level.texels.iter().all(|t| t[0] == 127.5)));
+    }
+
+    #[test]
+    fn test_sample() {
+        let texture = checkerboard(16);
+        let zero = Vector2::zeros();
+        // Top left texel is white, v going up the image, and repeating
+        let top_left = Vector2::new(0.5 / 16.0, 1.0 - 0.5 / 16.0);
+        assert_eq!(texture.sample(top_left, zero, zero, TextureFilter::Nearest), Color::WHITE);
+        assert_eq!(texture.sample(top_left + Vector2::new(1.0, -3.0), zero, zero, TextureFilter::Bilinear), Color::WHITE);
+        assert_eq!(texture.sample(Vector2::new(1.5 / 16.0, top_left.y), zero, zero, TextureFilter::Nearest), Color::BLACK);
+
+        // Between texels bilinear mixes them
+        let between = Vector2::new(1.0 / 16.0, top_left.y);
+        assert_eq!(texture.sample(between, zero, zero, TextureFilter::Bilinear), Color::RGB(128, 128, 128));
+
+        // A pixel covering many texels is gray, but nearest still picks one
+        let far = Vector2::new(0.5, 0.0);
+        for filter in [TextureFilter::Bilinear, TextureFilter::Trilinear, TextureFilter::Anisotropic(4)] {
+            assert_eq!(texture.sample(top_left, far, far, filter), Color::RGB(128, 128, 128));
+        }
+        assert_eq!(texture.sample(top_left, far, far, TextureFilter::Nearest), Color::WHITE);
+
+        // Footprint stretched along rows of a single color: anisotropic
+        // samples along the rows from the full image, trilinear blurs the
+        // rows together
+        let pixels: Vec<u8> = (0..16 * 16usize).flat_map(|i| [if (i / 16).is_multiple_of(2) { 255 } else { 0 }; 3]).collect();
+        let stripes = Texture::new(16, 16, &pixels);
+        let (dx, dy) = (Vector2::new(4.0 / 16.0, 0.0), Vector2::new(0.0, 1.0 / 16.0));
+        assert_eq!(stripes.sample(top_left, dx, dy, TextureFilter::Anisotropic(4)), Color::WHITE);
+        assert_eq!(stripes.sample(top_left, dx, dy, TextureFilter::Trilinear), Color::RGB(128, 128, 128));
+    }
+}
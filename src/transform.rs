@@ -0,0 +1,153 @@
+/*!
+# transform.rs
+
+Placement of an object in space: translation, rotation and scale.
+
+The rotation is kept as a unit quaternion, so it doesn't suffer from
+gimbal lock and can be interpolated smoothly between two orientations.
+*/
+
+use na::{Matrix3, Matrix4, UnitQuaternion, Vector3};
+
+/// Translation, rotation and non-uniform scale,
+/// applied in the order scale, rotation, translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    /// A transform that leaves everything in place
+    pub fn identity() -> Self {
+        Self {
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::repeat(1.0)
+        }
+    }
+
+    /// A transform that only moves things
+    #[allow(dead_code)]
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self { translation, ..Self::identity() }
+    }
+
+    /// A transform that only rotates things
+    #[allow(dead_code)]
+    pub fn from_rotation(rotation: UnitQuaternion<f32>) -> Self {
+        Self { rotation, ..Self::identity() }
+    }
+
+    /// Rotation from euler angles in radians, applied in the order x, y, z
+    pub fn euler_rotation(x: f32, y: f32, z: f32) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_axis_angle(&Vector3::z_axis(), z)
+            * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), y)
+            * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), x)
+    }
+
+    /// The 4x4 matrix applying the transform
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+
+    /// The 4x4 matrix undoing the transform
+    ///
+    /// Scales of 0 can't be undone, and are left as 0.
+    #[allow(dead_code)]
+    pub fn inverse_matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_nonuniform_scaling(&self.inverse_scale())
+            * self.rotation.inverse().to_homogeneous()
+            * Matrix4::new_translation(&-self.translation)
+    }
+
+    /// Matrix to transform normals, the inverse transpose of the
+    /// rotation and scale part of the transform
+    ///
+    /// Keeps normals perpendicular to surfaces under non-uniform scales,
+    /// the result still has to be normalized.
+    pub fn normal_matrix(&self) -> Matrix3<f32> {
+        self.rotation.to_rotation_matrix().into_inner()
+            * Matrix3::from_diagonal(&self.inverse_scale())
+    }
+
+    /// Interpolates between two transforms, `t` going from 0 to 1
+    ///
+    /// Translation and scale are interpolated linearly,
+    /// and the rotation spherically.
+    #[allow(dead_code)]
+    pub fn slerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: self.scale.lerp(&other.scale, t)
+        }
+    }
+
+    /// Component-wise inverse of the scale
+    fn inverse_scale(&self) -> Vector3<f32> {
+        self.scale.map(|s| if s == 0.0 { 0.0 } else { 1.0 / s })
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linear_transforms;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_matrix() {
+        let transform = Transform {
+            translation: Vector3::new(1.0, 2.0, 3.0),
+            rotation: Transform::euler_rotation(0.3, 0.0, 0.0),
+            scale: Vector3::new(2.0, 1.0, 0.5)
+        };
+
+        let expected = linear_transforms::translation_matrix(1.0, 2.0, 3.0)
+            * linear_transforms::rotation_matrix_x(0.3)
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 0.5));
+        assert!((transform.matrix() - expected).abs().max() < 1e-5);
+
+        let identity = transform.inverse_matrix() * transform.matrix();
+        assert!((identity - Matrix4::identity()).abs().max() < 1e-5);
+    }
+
+    #[test]
+    fn test_normal_matrix() {
+        // Squashing a slope keeps its normal perpendicular to it
+        let transform = Transform {
+            scale: Vector3::new(1.0, 0.5, 1.0),
+            ..Transform::identity()
+        };
+        let tangent = transform.matrix().fixed_slice::<3, 3>(0, 0) * Vector3::new(1.0, 1.0, 0.0);
+        let normal = transform.normal_matrix() * Vector3::new(-1.0, 1.0, 0.0);
+        assert!(tangent.dot(&normal).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_slerp() {
+        let a = Transform::identity();
+        let b = Transform {
+            translation: Vector3::new(2.0, 0.0, 0.0),
+            rotation: Transform::euler_rotation(0.0, PI / 2.0, 0.0),
+            scale: Vector3::repeat(3.0)
+        };
+
+        let half = a.slerp(&b, 0.5);
+        assert_eq!(half.translation, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(half.scale, Vector3::repeat(2.0));
+        assert!((half.rotation.angle() - PI / 4.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,155 @@
+/*!
+# transparency.rs
+
+Drawing of transparent triangles over the opaque ones.
+
+Transparent triangles are depth tested against the opaque ones, but
+don't write their depth, so what is behind them still gets drawn and
+shows through. Blending with alpha depends on the order, so by default
+they are sorted from the furthest to the closest and blended one after
+the other, which goes wrong where triangles cross each other.
+
+Weighted blended order-independent transparency (McGuire and Bavoil,
+2013) instead accumulates in each pixel an average of the colors of the
+alpha blended triangles, weighted by their opacity and closeness, and how
+much of the background they let through, then composites it over the
+opaque surfaces once. It is an approximation, but never pops as the
+triangles change order. Additive and multiplied triangles don't depend
+on the order, they are blended directly in both methods.
+*/
+
+use sdl2::pixels::Color;
+
+use crate::framebuffer::Framebuffer;
+use crate::material::BlendMode;
+
+/// How alpha blended triangles are put together
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transparency {
+    /// Blended one after the other from the furthest to the closest
+    Sorted,
+    /// Weighted blended order-independent transparency
+    Weighted
+}
+
+impl Transparency {
+    /// The other method
+    pub fn next(self) -> Self {
+        match self {
+            Transparency::Sorted => Transparency::Weighted,
+            Transparency::Weighted => Transparency::Sorted
+        }
+    }
+}
+
+/// Combines a color of the given opacity with the color behind it
+pub fn blend(mode: BlendMode, destination: [u8; 3], source: Color, alpha: f32) -> [u8; 3] {
+    let mix = |d: u8, s: u8| {
+        let (d, s) = (d as f32, s as f32);
+        let value = match mode {
+            BlendMode::Alpha => d + (s - d) * alpha,
+            BlendMode::Additive => d + s * alpha,
+            BlendMode::Multiply => d * (1.0 - alpha + alpha * s / 255.0)
+        };
+        value.round().min(255.0) as u8
+    };
+    [mix(destination[0], source.r), mix(destination[1], source.g), mix(destination[2], source.b)]
+}
+
+/// Weight of a surface in the average of its pixel, from its opacity and
+/// distance to the camera, so the closest surfaces dominate
+///
+/// Equation 7 of the paper, for distances of up to a few hundred units.
+fn weight(alpha: f32, distance: f32) -> f32 {
+    let falloff = 10.0 / (1e-5 + (distance / 5.0).powi(2) + (distance / 200.0).powi(6));
+    alpha * falloff.clamp(1e-2, 3e3)
+}
+
+/// Sums of the transparent surfaces drawn in each pixel of a framebuffer,
+/// for weighted blended transparency
+pub struct Accumulation {
+    /// Red, green and blue premultiplied by the opacity, and the opacity,
+    /// all multiplied by the weight
+    color: Vec<[f32; 4]>,
+    /// Fraction of the background still showing through
+    revealage: Vec<f32>
+}
+
+impl Accumulation {
+    /// Sums for a framebuffer of the given size, with nothing drawn yet
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            color: vec![[0.0; 4]; width * height],
+            revealage: vec![1.0; width * height]
+        }
+    }
+
+    /// Adds a surface of the given color and opacity, at that distance
+    /// from the camera, to the pixel at the index
+    pub fn add(&mut self, i: usize, color: Color, alpha: f32, distance: f32) {
+        // Premultiplied by the opacity
+        let w = alpha * weight(alpha, distance);
+        let sum = &mut self.color[i];
+        sum[0] += color.r as f32 * w;
+        sum[1] += color.g as f32 * w;
+        sum[2] += color.b as f32 * w;
+        sum[3] += w;
+        self.revealage[i] *= 1.0 - alpha;
+    }
+
+    /// Blends the average of the surfaces drawn in each pixel over it,
+    /// by how much they cover together
+    pub fn composite(&self, framebuffer: &mut Framebuffer) {
+        for (i, (sum, &revealage)) in self.color.iter().zip(self.revealage.iter()).enumerate() {
+            if sum[3] == 0.0 {
+                continue;
+            }
+            let channel = |c: f32| (c / sum[3]).round().min(255.0) as u8;
+            let average = Color::RGB(channel(sum[0]), channel(sum[1]), channel(sum[2]));
+            let pixel = &mut framebuffer.pixels[i * 3..i * 3 + 3];
+            let blended = blend(BlendMode::Alpha, [pixel[0], pixel[1], pixel[2]], average, 1.0 - revealage);
+            pixel.copy_from_slice(&blended);
+        }
+    }
+}
+
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend() {
+        let gray = [100, 100, 100];
+        assert_eq!(blend(BlendMode::Alpha, gray, Color::RGB(200, 0, 100), 0.5), [150, 50, 100]);
+        assert_eq!(blend(BlendMode::Additive, gray, Color::RGB(200, 0, 100), 0.5), [200, 100, 150]);
+        assert_eq!(blend(BlendMode::Additive, gray, Color::WHITE, 1.0), [255, 255, 255]);
+        assert_eq!(blend(BlendMode::Multiply, gray, Color::RGB(0, 255, 51), 1.0), [0, 100, 20]);
+        assert_eq!(blend(BlendMode::Multiply, gray, Color::BLACK, 0.5), [50, 50, 50]);
+    }
+
+    #[test]
+    fn test_accumulation() {
+        // Two surfaces at the same distance in any order give the same
+        // pixel, and the second pixel is left alone
+        let render = |surfaces: &[(Color, f32)]| {
+            let mut framebuffer = Framebuffer::new(2, 1);
+            let mut accumulation = Accumulation::new(2, 1);
+            for &(color, alpha) in surfaces {
+                accumulation.add(0, color, alpha, 10.0);
+            }
+            accumulation.composite(&mut framebuffer);
+            framebuffer
+        };
+        let red_blue = render(&[(Color::RED, 0.5), (Color::BLUE, 0.5)]);
+        let blue_red = render(&[(Color::BLUE, 0.5), (Color::RED, 0.5)]);
+        assert_eq!(red_blue.pixels, blue_red.pixels);
+        assert_eq!(red_blue.get(0, 0), Color::RGB(96, 0, 96));
+        assert_eq!(red_blue.get(1, 0), Color::BLACK);
+
+        // A single surface blends like alpha blending does
+        let red = render(&[(Color::RED, 0.5)]);
+        assert_eq!(red.get(0, 0), Color::RGB(128, 0, 0));
+    }
+}